@@ -1,16 +1,21 @@
 #!/usr/bin/env bash
 
 EVENT=$1
-ACTIVE_MINUTES=$2
+ACTIVITY=$2
 DEBT=$3
+# "minutes" or "steps"
+UNIT=$4
 
 TITLE=""
-MESSAGE="Active Minutes: ${ACTIVE_MINUTES}; Debt: ${DEBT}"
+MESSAGE="Activity: ${ACTIVITY} ${UNIT}; Debt: ${DEBT} ${UNIT}"
 
 case ${EVENT} in
 "Normal")
     TITLE="No debt this time. Well done!"
-    MESSAGE="You've fulfilled your hourly norm.\nActive minutes: ${ACTIVE_MINUTES}"
+    MESSAGE="You've fulfilled your hourly norm.\nActivity: ${ACTIVITY} ${UNIT}"
+;;
+"DebtWarning")
+    TITLE="Time to move, the debt is going to be collected soon!"
 ;;
 "DebtCollection")
     TITLE="Hey! Go ahead and move your arse!"
@@ -18,6 +23,10 @@ case ${EVENT} in
 "DebtCollectionPaused")
     TITLE="Relax for a bit, but remember: I'm watching you!"
 ;;
+"Paused")
+    TITLE="Debt collection is paused"
+    MESSAGE="Take care!"
+;;
 esac
 
 osascript -e "display notification \"$MESSAGE\" with title \"$TITLE\""
@@ -1,4 +1,4 @@
-use driver::{CallbackTrigger, Driver, State};
+use driver::{CallbackTrigger, Driver, State, Unit, MAX_SEVERITY};
 use failure::{format_err, Error};
 use log::{debug, error, info, trace, warn};
 use serde::Deserialize;
@@ -25,7 +25,11 @@ struct Options {
     )]
     plugins: PathBuf,
 
-    /// Headmaster Url
+    /// Headmaster user api key
+    #[structopt(short = "k", long = "api-key")]
+    api_key: Option<String>,
+
+    /// Headmaster user summary Url, e.g. http://localhost:8081/api/v1/users/{id}/summary
     url: String,
 }
 
@@ -41,7 +45,12 @@ fn discover_plugins<P: AsRef<Path>>(base_dir: P) -> Result<Vec<Plugin>, Error> {
 
     #[derive(Deserialize)]
     struct Manifest {
+        /// states the plugin is run in
+        #[serde(default)]
         triggers: Vec<CallbackTrigger>,
+        /// the plugin is run while the debt collection severity is at least that,
+        /// kept apart from the triggers as TOML arrays can't mix the names with the tables
+        min_severity: Option<u32>,
         enabled: bool,
     }
 
@@ -114,7 +123,8 @@ fn discover_plugins<P: AsRef<Path>>(base_dir: P) -> Result<Vec<Plugin>, Error> {
             continue;
         }
 
-        for trigger in manifest.triggers {
+        let severity = manifest.min_severity.map(CallbackTrigger::Severity);
+        for trigger in manifest.triggers.into_iter().chain(severity) {
             plugins.push(Plugin {
                 trigger,
                 path: PathBuf::from(plugin),
@@ -145,14 +155,20 @@ fn execute_plugin(plugin: &Path, state: State) -> Result<std::process::ExitStatu
 
     let (discriminant, stat) = match state {
         State::Normal(stat) => ("Normal", stat),
+        State::DebtWarning(stat) => ("DebtWarning", stat),
         State::DebtCollection(stat) => ("DebtCollection", stat),
         State::DebtCollectionPaused(stat) => ("DebtCollectionPaused", stat),
+        State::Paused(stat) => ("Paused", stat),
     };
 
-    let (active, debt) = (format!("{}", stat.active_minutes), format!("{}", stat.debt));
+    let (active, debt) = (format!("{}", stat.activity()), format!("{}", stat.debt));
+    let unit = match stat.unit {
+        Unit::Minutes => "minutes",
+        Unit::Steps => "steps",
+    };
 
     let status = Command::new(plugin)
-        .args(&[discriminant, &active, &debt])
+        .args(&[discriminant, &active, &debt, unit])
         .status()?;
 
     Ok(status)
@@ -163,6 +179,9 @@ fn main() {
     env_logger::init();
 
     let mut driver = Driver::new(&options.url, Duration::from_secs(options.period));
+    if let Some(api_key) = &options.api_key {
+        driver.set_api_key(api_key);
+    }
 
     let callback_factory = |event| {
         let base_path = options.plugins.clone();
@@ -182,6 +201,10 @@ fn main() {
         CallbackTrigger::Normal,
         callback_factory(CallbackTrigger::Normal),
     );
+    driver.add_callback(
+        CallbackTrigger::DebtWarning,
+        callback_factory(CallbackTrigger::DebtWarning),
+    );
     driver.add_callback(
         CallbackTrigger::DebtCollection,
         callback_factory(CallbackTrigger::DebtCollection),
@@ -190,6 +213,16 @@ fn main() {
         CallbackTrigger::DebtCollectionPaused,
         callback_factory(CallbackTrigger::DebtCollectionPaused),
     );
+    driver.add_callback(
+        CallbackTrigger::Paused,
+        callback_factory(CallbackTrigger::Paused),
+    );
+    for level in 1..=MAX_SEVERITY {
+        driver.add_callback(
+            CallbackTrigger::Severity(level),
+            callback_factory(CallbackTrigger::Severity(level)),
+        );
+    }
 
     driver.run();
 }
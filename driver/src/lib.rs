@@ -3,24 +3,32 @@ use log::{debug, error, info};
 use serde::{Deserialize, Serialize};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-pub use headmaster::{HourSummary, State, Summary};
+pub use headmaster::{HourSummary, State, Summary, Unit, MAX_SEVERITY};
 
 pub type Callback = Box<dyn Fn(State) -> Result<(), Error>>;
 
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
 pub enum CallbackTrigger {
     Normal,
+    DebtWarning,
     DebtCollection,
     DebtCollectionPaused,
+    Paused,
+    /// debt collection severity is at least the level
+    Severity(u32),
 }
 
 impl CallbackTrigger {
-    fn is_triggered_for(self, state: &State) -> bool {
+    fn is_triggered_for(self, state: &State, severity: u32) -> bool {
         match self {
             CallbackTrigger::Normal => match state {
                 State::Normal(..) => true,
                 _ => false,
             },
+            CallbackTrigger::DebtWarning => match state {
+                State::DebtWarning(..) => true,
+                _ => false,
+            },
             CallbackTrigger::DebtCollection => match state {
                 State::DebtCollection(..) => true,
                 _ => false,
@@ -29,15 +37,22 @@ impl CallbackTrigger {
                 State::DebtCollectionPaused(..) => true,
                 _ => false,
             },
+            CallbackTrigger::Paused => match state {
+                State::Paused(..) => true,
+                _ => false,
+            },
+            CallbackTrigger::Severity(level) => level > 0 && severity >= level,
         }
     }
 }
 
 pub struct Driver {
     url: String,
+    api_key: Option<String>,
     period: Duration,
     callbacks: Vec<(CallbackTrigger, Callback)>,
     prev_state: Option<State>,
+    prev_severity: u32,
 }
 
 impl Driver {
@@ -45,12 +60,19 @@ impl Driver {
         let url = url.as_ref().to_owned();
         Driver {
             url,
+            api_key: None,
             period,
             callbacks: vec![],
             prev_state: None,
+            prev_severity: 0,
         }
     }
 
+    /// Authorize requests to headmaster with the user's api key
+    pub fn set_api_key<A: AsRef<str>>(&mut self, api_key: A) {
+        self.api_key = Some(api_key.as_ref().to_owned());
+    }
+
     pub fn add_callback(&mut self, trigger: CallbackTrigger, callback: Callback) {
         self.callbacks.push((trigger, callback));
         debug!("registered callback for {:?}", trigger);
@@ -71,26 +93,36 @@ impl Driver {
         use std::mem::discriminant;
 
         debug!("querying {}", self.url);
-        let response = reqwest::get(&self.url)
+        let mut request = reqwest::Client::new().get(&self.url);
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        let response = request
+            .send()
+            .and_then(|response| response.error_for_status())
             .map_err(|e| format_err!("failed to GET {}: {}", self.url, e))?;
 
         let summary: Summary = serde_json::from_reader(response)
             .map_err(|e| format_err!("failed to deserialize response: {}", e))?;
-        let state = summary.state;
-        info!("current state is {:?}", state);
+        let (state, severity) = (summary.state, summary.severity);
+        info!("current state is {:?}, severity {}", state, severity);
 
         if self.prev_state.map_or(false, |prev| {
-            discriminant(&prev) == discriminant(&state) && !state.is_debt_collection()
+            discriminant(&prev) == discriminant(&state)
+                && self.prev_severity == severity
+                && !state.is_debt_collection()
         }) {
             info!("state is the same, callbacks are not triggered");
             return Ok(());
         }
 
         self.prev_state = Some(state);
+        self.prev_severity = severity;
 
         self.callbacks
             .iter()
-            .filter(|(trigger, _)| trigger.is_triggered_for(&state))
+            .filter(|(trigger, _)| trigger.is_triggered_for(&state, severity))
             .inspect(|(trigger, _)| info!("triggering callback for event {:?}", trigger))
             .for_each(|(_, callback)| {
                 if let Err(e) = callback(state) {
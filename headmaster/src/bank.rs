@@ -0,0 +1,119 @@
+use crate::config::Bank;
+use chrono::{Duration, NaiveDateTime};
+use std::collections::VecDeque;
+
+/// Overtime credit of the day window, spent oldest first
+pub struct CreditBank {
+    config: Bank,
+    /// credited minutes with the time they were earned at
+    deposits: VecDeque<(NaiveDateTime, u32)>,
+}
+
+impl CreditBank {
+    pub fn new(config: Bank) -> Self {
+        CreditBank {
+            config,
+            deposits: VecDeque::new(),
+        }
+    }
+
+    /// Credit the overtime earned at `time`, anything above the cap is lost
+    pub fn deposit(&mut self, time: NaiveDateTime, minutes: u32) {
+        let minutes = u32::min(minutes, self.config.cap - self.balance(time));
+        if minutes > 0 {
+            self.deposits.push_back((time, minutes));
+        }
+    }
+
+    /// Prepay up to the configured share of the `requirement`, returns the minutes spent
+    pub fn withdraw(&mut self, time: NaiveDateTime, requirement: u32, needed: u32) -> u32 {
+        let limit = requirement * self.config.share_percent / 100;
+        let mut amount = u32::min(u32::min(limit, needed), self.balance(time));
+
+        let withdrawn = amount;
+        while amount > 0 {
+            let deposit = self
+                .deposits
+                .front_mut()
+                .expect("balance covers the amount");
+            let spent = u32::min(deposit.1, amount);
+            deposit.1 -= spent;
+            amount -= spent;
+            if deposit.1 == 0 {
+                self.deposits.pop_front();
+            }
+        }
+
+        withdrawn
+    }
+
+    /// Credit available at `time`, expired deposits are dropped
+    pub fn balance(&mut self, time: NaiveDateTime) -> u32 {
+        let expiry = Duration::hours(self.config.expiry_hours);
+        while let Some(&(earned, _)) = self.deposits.front() {
+            if earned + expiry > time {
+                break;
+            }
+            self.deposits.pop_front();
+        }
+
+        self.deposits.iter().map(|(_, minutes)| minutes).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn bank(cap: u32) -> CreditBank {
+        CreditBank::new(Bank {
+            cap,
+            expiry_hours: 2,
+            share_percent: 50,
+        })
+    }
+
+    fn at(hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd(2019, 1, 7).and_hms(hour, 0, 0)
+    }
+
+    #[test]
+    fn deposits_are_capped() {
+        let mut bank = bank(20);
+        bank.deposit(at(10), 15);
+        bank.deposit(at(11), 15);
+        assert_eq!(bank.balance(at(11)), 20);
+    }
+
+    #[test]
+    fn deposits_expire() {
+        let mut bank = bank(60);
+        bank.deposit(at(10), 10);
+        bank.deposit(at(11), 5);
+        assert_eq!(bank.balance(at(11)), 15);
+        assert_eq!(bank.balance(at(12)), 5);
+        assert_eq!(bank.balance(at(13)), 0);
+    }
+
+    #[test]
+    fn withdrawal_is_limited_by_share_and_need() {
+        let mut bank = bank(60);
+        bank.deposit(at(10), 30);
+        // Half of the 10 minutes requirement
+        assert_eq!(bank.withdraw(at(11), 10, 10), 5);
+        assert_eq!(bank.withdraw(at(11), 10, 2), 2);
+        assert_eq!(bank.balance(at(11)), 23);
+    }
+
+    #[test]
+    fn oldest_deposits_are_spent_first() {
+        let mut bank = bank(60);
+        bank.deposit(at(10), 4);
+        bank.deposit(at(11), 10);
+        assert_eq!(bank.withdraw(at(11), 20, 10), 10);
+        // The rest of the later deposit outlives the earlier one
+        assert_eq!(bank.balance(at(12)), 4);
+        assert_eq!(bank.balance(at(13)), 0);
+    }
+}
@@ -0,0 +1,364 @@
+use crate::config::{CalendarKind, CalendarSource};
+use chrono::{Duration, NaiveDate, NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
+use failure::{format_err, Error};
+use headmaster::TrackingDisabledReason;
+use log::{debug, warn};
+use std::fs;
+use std::time::SystemTime;
+
+/// Calendar event excluding the time it spans from the debt calculation
+#[derive(Debug, Copy, Clone)]
+pub struct Event {
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub reason: TrackingDisabledReason,
+}
+
+/// iCalendar file, re-read every time it's modified
+pub struct Calendar {
+    source: CalendarSource,
+    modified: Option<SystemTime>,
+    events: Vec<Event>,
+}
+
+impl Calendar {
+    pub fn new(source: CalendarSource) -> Self {
+        Calendar {
+            source,
+            modified: None,
+            events: vec![],
+        }
+    }
+
+    /// Re-read the file if it was modified since the last read, event times are converted into the `tz`.
+    /// Previously read events are kept if the file can't be read.
+    pub fn refresh(&mut self, tz: Tz) -> Result<(), Error> {
+        let path = &self.source.path;
+        let modified = fs::metadata(path)?.modified()?;
+        if self.modified == Some(modified) {
+            return Ok(());
+        }
+
+        debug!("reading calendar {}", path.display());
+        let contents = fs::read_to_string(path)?;
+        let kind = self.source.kind;
+        self.events = parse_events(&contents, tz)?
+            .into_iter()
+            .filter_map(|event| {
+                let (start, end) = event.interval()?;
+                let reason = match kind {
+                    CalendarKind::DaysOff => event.days_off_reason()?,
+                    CalendarKind::Busy if event.is_busy() => TrackingDisabledReason::Meeting,
+                    CalendarKind::Busy => return None,
+                };
+                Some(Event { start, end, reason })
+            })
+            .collect();
+        self.modified = Some(modified);
+
+        Ok(())
+    }
+
+    /// Whether the requirement of the hours covered by the events is moved into the next free hour
+    pub fn shifts_requirement(&self) -> bool {
+        self.source.shift_requirement
+    }
+
+    /// Parts of the events falling into the `from..to` range
+    pub fn events(&self, from: NaiveDateTime, to: NaiveDateTime) -> Vec<Event> {
+        self.events
+            .iter()
+            .filter(|event| event.start < to && event.end > from)
+            .map(|event| Event {
+                start: NaiveDateTime::max(event.start, from),
+                end: NaiveDateTime::min(event.end, to),
+                reason: event.reason,
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct RawEvent {
+    start: Option<NaiveDateTime>,
+    end: Option<NaiveDateTime>,
+    all_day: bool,
+    summary: String,
+    categories: Vec<String>,
+    transparent: bool,
+    cancelled: bool,
+}
+
+impl RawEvent {
+    fn interval(&self) -> Option<(NaiveDateTime, NaiveDateTime)> {
+        let start = self.start?;
+        // All-day events without end last one day, timed ones are instant
+        let end = self.end.unwrap_or_else(|| {
+            if self.all_day {
+                start + Duration::days(1)
+            } else {
+                start
+            }
+        });
+        Some((start, end))
+    }
+
+    /// Transparent events do not block the time: reminders, birthdays, etc.
+    fn is_busy(&self) -> bool {
+        !self.transparent && !self.cancelled
+    }
+
+    /// Events are recognized as days off by the tags in the categories or in the summary,
+    /// cancelled days off are worked as usual
+    fn days_off_reason(&self) -> Option<TrackingDisabledReason> {
+        if self.cancelled {
+            return None;
+        }
+
+        let tags = [
+            ("holiday", TrackingDisabledReason::Holiday),
+            ("vacation", TrackingDisabledReason::Vacation),
+            ("sick", TrackingDisabledReason::SickLeave),
+        ];
+
+        let texts = self
+            .categories
+            .iter()
+            .chain(Some(&self.summary))
+            .map(|text| text.to_lowercase())
+            .collect::<Vec<_>>();
+
+        tags.iter()
+            .find(|(tag, _)| texts.iter().any(|text| text.contains(tag)))
+            .map(|(_, reason)| *reason)
+    }
+}
+
+/// Minimal iCalendar (RFC 5545) reader: only VEVENT's DTSTART, DTEND, SUMMARY, CATEGORIES, TRANSP
+/// and STATUS are read.
+/// Recurrence rules are not supported.
+fn parse_events(contents: &str, tz: Tz) -> Result<Vec<RawEvent>, Error> {
+    // Unfold the lines: long lines are split into the several ones starting with a whitespace
+    let mut lines: Vec<String> = Vec::new();
+    for line in contents.lines() {
+        match lines.last_mut() {
+            Some(last) if line.starts_with(' ') || line.starts_with('\t') => {
+                last.push_str(&line[1..])
+            }
+            _ => lines.push(line.to_owned()),
+        }
+    }
+
+    let mut events = Vec::new();
+    let mut current: Option<RawEvent> = None;
+
+    for line in &lines {
+        let (name, value) = match line.find(':') {
+            Some(i) => (&line[..i], &line[i + 1..]),
+            None => continue,
+        };
+        // Only the TZID property parameter is read, VALUE=DATE is recognized by the value itself
+        let mut params = name.split(';');
+        let name = params.next().unwrap_or_default().to_uppercase();
+        let tzid = params
+            .filter_map(|param| {
+                let (key, value) = param.split_at(param.find('=')?);
+                if key.eq_ignore_ascii_case("TZID") {
+                    Some(value[1..].trim_matches('"'))
+                } else {
+                    None
+                }
+            })
+            .next();
+
+        if name == "BEGIN" && value == "VEVENT" {
+            current = Some(RawEvent::default());
+            continue;
+        }
+
+        let event = match current.as_mut() {
+            Some(event) => event,
+            None => continue,
+        };
+
+        match name.as_str() {
+            "END" if value == "VEVENT" => events.extend(current.take()),
+            "DTSTART" => {
+                event.all_day = value.len() == 8;
+                event.start = Some(parse_datetime(value, tzid, tz)?);
+            }
+            "DTEND" => event.end = Some(parse_datetime(value, tzid, tz)?),
+            "SUMMARY" => event.summary = value.to_owned(),
+            "CATEGORIES" => event
+                .categories
+                .extend(value.split(',').map(|c| c.trim().to_owned())),
+            "TRANSP" => event.transparent = value == "TRANSPARENT",
+            "STATUS" => event.cancelled = value == "CANCELLED",
+            "RRULE" => warn!(
+                "recurring calendar event {:?} is read as a single one",
+                event.summary
+            ),
+            _ => (),
+        }
+    }
+
+    Ok(events)
+}
+
+/// Parse DATE (20190101), local DATE-TIME (20190101T100000) or UTC DATE-TIME (20190101T100000Z) into the `tz`.
+/// Local DATE-TIME is in the `tzid` timezone if it's set, floating ones are in the `tz`
+fn parse_datetime(value: &str, tzid: Option<&str>, tz: Tz) -> Result<NaiveDateTime, Error> {
+    let invalid = |e| format_err!("invalid calendar date {:?}: {}", value, e);
+
+    if value.len() == 8 {
+        let date = NaiveDate::parse_from_str(value, "%Y%m%d").map_err(invalid)?;
+        Ok(date.and_hms(0, 0, 0))
+    } else if value.ends_with('Z') {
+        let utc = NaiveDateTime::parse_from_str(&value[..value.len() - 1], "%Y%m%dT%H%M%S")
+            .map_err(invalid)?;
+        Ok(tz.from_utc_datetime(&utc).naive_local())
+    } else {
+        let local = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").map_err(invalid)?;
+        let event_tz = match tzid.map(|tzid| (tzid, tzid.parse::<Tz>())) {
+            Some((_, Ok(event_tz))) => event_tz,
+            Some((tzid, Err(_))) => {
+                warn!(
+                    "unknown calendar timezone {:?}, {} is read as a local time",
+                    tzid, value
+                );
+                return Ok(local);
+            }
+            None => return Ok(local),
+        };
+        // Time skipped by a DST transition is shifted by the transition
+        let time = event_tz
+            .from_local_datetime(&local)
+            .earliest()
+            .or_else(|| {
+                event_tz
+                    .from_local_datetime(&(local + Duration::hours(1)))
+                    .earliest()
+            })
+            .ok_or_else(|| {
+                format_err!("nonexistent calendar time {} in {}", value, event_tz.name())
+            })?;
+        Ok(time.with_timezone(&tz).naive_local())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calendar(events: &str) -> String {
+        format!(
+            "BEGIN:VCALENDAR\r\nVERSION:2.0\r\n{}END:VCALENDAR\r\n",
+            events
+        )
+    }
+
+    fn datetime(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn intervals(events: &[RawEvent]) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+        events.iter().filter_map(RawEvent::interval).collect()
+    }
+
+    #[test]
+    fn unfolds_long_lines() {
+        let contents = calendar(
+            "BEGIN:VEVENT\r\nDTSTART;VALUE=DATE:20190107\r\nSUMMARY:Public \r\n holi\r\n\tday\r\nEND:VEVENT\r\n",
+        );
+        let events = parse_events(&contents, chrono_tz::UTC).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].summary, "Public holiday");
+        assert_eq!(
+            events[0].days_off_reason(),
+            Some(TrackingDisabledReason::Holiday)
+        );
+    }
+
+    #[test]
+    fn all_day_event_lasts_a_day() {
+        let contents = calendar("BEGIN:VEVENT\nDTSTART;VALUE=DATE:20190107\nEND:VEVENT\n");
+        let events = parse_events(&contents, chrono_tz::UTC).unwrap();
+        assert!(events[0].all_day);
+        assert_eq!(
+            intervals(&events),
+            vec![(datetime("2019-01-07 00:00"), datetime("2019-01-08 00:00"))]
+        );
+    }
+
+    #[test]
+    fn floating_time_is_local() {
+        let contents =
+            calendar("BEGIN:VEVENT\nDTSTART:20190107T100000\nDTEND:20190107T113000\nEND:VEVENT\n");
+        let events = parse_events(&contents, chrono_tz::Europe::Moscow).unwrap();
+        assert!(!events[0].all_day);
+        assert_eq!(
+            intervals(&events),
+            vec![(datetime("2019-01-07 10:00"), datetime("2019-01-07 11:30"))]
+        );
+    }
+
+    #[test]
+    fn utc_time_is_converted() {
+        let contents = calendar(
+            "BEGIN:VEVENT\nDTSTART:20190107T100000Z\nDTEND:20190107T110000Z\nEND:VEVENT\n",
+        );
+        let events = parse_events(&contents, chrono_tz::Europe::Moscow).unwrap();
+        assert_eq!(
+            intervals(&events),
+            vec![(datetime("2019-01-07 13:00"), datetime("2019-01-07 14:00"))]
+        );
+    }
+
+    #[test]
+    fn tzid_time_is_converted() {
+        let contents = calendar(
+            "BEGIN:VEVENT\nDTSTART;TZID=America/New_York:20190107T100000\n\
+             DTEND;TZID=\"America/New_York\":20190107T110000\nEND:VEVENT\n",
+        );
+        let events = parse_events(&contents, chrono_tz::Europe::Moscow).unwrap();
+        assert_eq!(
+            intervals(&events),
+            vec![(datetime("2019-01-07 18:00"), datetime("2019-01-07 19:00"))]
+        );
+    }
+
+    #[test]
+    fn unknown_tzid_is_local() {
+        let contents =
+            calendar("BEGIN:VEVENT\nDTSTART;TZID=Nowhere/Special:20190107T100000\nEND:VEVENT\n");
+        let events = parse_events(&contents, chrono_tz::UTC).unwrap();
+        assert_eq!(events[0].start, Some(datetime("2019-01-07 10:00")));
+    }
+
+    #[test]
+    fn cancelled_and_transparent_events_are_free() {
+        let contents = calendar(
+            "BEGIN:VEVENT\nDTSTART:20190107T100000\nSTATUS:CANCELLED\nEND:VEVENT\n\
+             BEGIN:VEVENT\nDTSTART:20190107T110000\nTRANSP:TRANSPARENT\nEND:VEVENT\n\
+             BEGIN:VEVENT\nDTSTART:20190107T120000\nTRANSP:OPAQUE\nEND:VEVENT\n",
+        );
+        let events = parse_events(&contents, chrono_tz::UTC).unwrap();
+        let busy = events.iter().map(RawEvent::is_busy).collect::<Vec<_>>();
+        assert_eq!(busy, vec![false, false, true]);
+    }
+
+    #[test]
+    fn cancelled_days_off_are_worked() {
+        let contents = calendar(
+            "BEGIN:VEVENT\nDTSTART;VALUE=DATE:20190107\nSUMMARY:Vacation\nSTATUS:CANCELLED\nEND:VEVENT\n\
+             BEGIN:VEVENT\nDTSTART;VALUE=DATE:20190108\nSUMMARY:Vacation\nEND:VEVENT\n",
+        );
+        let events = parse_events(&contents, chrono_tz::UTC).unwrap();
+        let reasons = events
+            .iter()
+            .map(RawEvent::days_off_reason)
+            .collect::<Vec<_>>();
+        assert_eq!(reasons, vec![None, Some(TrackingDisabledReason::Vacation)]);
+    }
+}
@@ -0,0 +1,271 @@
+use crate::config::{Config, DebtPolicyKind, Layer, Schedule, User};
+use chrono::{Duration, Timelike};
+use failure::{format_err, Error};
+use headmaster::Unit;
+use std::path::Path;
+
+/// Report all the problems of the config at `path` with their lines, or explain the configured rules
+pub fn run(path: &Path) -> Result<(), Error> {
+    let (config, layers) = Config::read(path)?;
+
+    let problems = config.problems();
+    for problem in &problems {
+        println!("{}: {}", location(&layers, &problem.field), problem);
+    }
+    if !problems.is_empty() {
+        return Err(format_err!("{} problem(s) found", problems.len()));
+    }
+
+    for user in &config.users {
+        println!("{}", explain(user));
+    }
+    Ok(())
+}
+
+/// Where the `field` is set: the last layer setting it explicitly, otherwise the last file with its closest enclosing table
+fn location(layers: &[Layer], field: &str) -> String {
+    let files = || {
+        layers.iter().rev().filter_map(|layer| match layer {
+            Layer::File { path, contents } => Some((path, contents)),
+            Layer::Env { .. } => None,
+        })
+    };
+
+    for layer in layers.iter().rev() {
+        match layer {
+            Layer::Env { name, field: set } if set.eq_ignore_ascii_case(field) => {
+                return format!("environment variable {}", name);
+            }
+            Layer::File { path, contents } => {
+                if let Some((line, true)) = locate(contents, field) {
+                    return format!("{}:{}", path.display(), line);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    let enclosing = files().find_map(|(path, contents)| {
+        locate(contents, field).map(|(line, _)| format!("{}:{}", path.display(), line))
+    });
+    enclosing
+        .or_else(|| files().last().map(|(path, _)| path.display().to_string()))
+        .unwrap_or_default()
+}
+
+/// Line number of the `field` (e.g. `users.default.weekdays[1].limits.debt_limit`) in the TOML `contents`,
+/// and whether the field itself is set there. Points to the closest enclosing table if it's not
+fn locate(contents: &str, field: &str) -> Option<(usize, bool)> {
+    let lines = contents.lines().collect::<Vec<_>>();
+    let is_header = |line: &str, name: &str| {
+        let line = line.trim();
+        let table = line.trim_matches(|c| c == '[' || c == ']');
+        line.starts_with('[') && (table == name || table.ends_with(&format!(".{}", name)))
+    };
+
+    // Users are found by the id, the fields are looked for within the user's section
+    let mut segments = field.split('.');
+    if segments.next() != Some("users") {
+        return None;
+    }
+    let id = segments.next()?;
+    let user_line = lines.iter().position(|line| {
+        let line = line.replace(' ', "");
+        line == format!("id=\"{}\"", id) || line == format!("id='{}'", id)
+    })?;
+    let user_end = lines
+        .iter()
+        .skip(user_line)
+        .position(|line| line.trim() == "[[users]]")
+        .map_or(lines.len(), |i| user_line + i);
+
+    let mut found = user_line;
+    let mut exact = true;
+    for segment in segments {
+        let (name, index) = match segment.find('[') {
+            Some(i) => (
+                &segment[..i],
+                segment[i + 1..segment.len() - 1].parse().ok(),
+            ),
+            None => (segment, None),
+        };
+
+        let key = lines[found..user_end].iter().position(|line| {
+            let line = line.trim_start();
+            line.starts_with(name) && line[name.len()..].trim_start().starts_with('=')
+        });
+        let mut tables = lines[found..user_end]
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| is_header(line, name))
+            .map(|(i, _)| i);
+        // N-th entry of an array of tables
+        let table = match index {
+            Some(index) => tables.nth(index),
+            None => tables.next(),
+        };
+        let position = match (key, table) {
+            (Some(key), Some(table)) => Some(usize::min(key, table)),
+            (key, table) => key.or(table),
+        };
+        // Tables may be inlined or omitted, their fields are looked for further on
+        exact = position.is_some();
+        if let Some(i) = position {
+            found += i;
+        }
+    }
+
+    Some((found + 1, exact))
+}
+
+/// Plain-English description of the user's rules on a typical day
+fn explain(user: &User) -> String {
+    let mut lines = vec![format!("User {}:", user.id)];
+
+    let schedule = user.schedule(chrono::Weekday::Mon);
+    lines.extend(explain_schedule(&schedule));
+
+    if user.grace_period_minutes > 0 {
+        lines.push(format!(
+            "A new debt is collected {} minutes after the hour ends, if it's not paid off by then.",
+            user.grace_period_minutes
+        ));
+    }
+    if let Some(bank) = &user.bank {
+        lines.push(format!(
+            "Activity above the hourly maximum is banked, up to {} in total, for {} hours; \
+             it may prepay up to {}% of a later hour's requirement.",
+            bank.cap, bank.expiry_hours, bank.share_percent
+        ));
+    }
+    if let Some(decay) = &user.decay {
+        lines.push(format!(
+            "Every hour with its own requirement met forgives {}% of the outstanding debt.",
+            decay.percent
+        ));
+    }
+    if !user.severity_levels.is_empty() {
+        lines.push(format!(
+            "Debt collection escalates through {} severity levels.",
+            user.severity_levels.len() + 1
+        ));
+    }
+    if user.pause.weekly_quota_hours > 0 {
+        lines.push(format!(
+            "Debt collection may be paused for {} hours a week.",
+            user.pause.weekly_quota_hours
+        ));
+    }
+
+    for weekdays in &user.weekdays {
+        let days = weekdays
+            .days
+            .iter()
+            .map(|day| format!("{:?}", day))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let schedule = match weekdays.days.first() {
+            Some(&weekday) => user.schedule(weekday),
+            None => continue,
+        };
+        if schedule.tracking_disabled {
+            lines.push(format!("On {}: no debt is collected.", days));
+        } else {
+            lines.push(format!("On {}:", days));
+            lines.extend(
+                explain_schedule(&schedule)
+                    .into_iter()
+                    .map(|l| format!("  {}", l)),
+            );
+        }
+    }
+
+    lines.join("\n")
+}
+
+fn explain_schedule(schedule: &Schedule) -> Vec<String> {
+    let Schedule { day, limits, .. } = schedule;
+    let unit = match limits.unit {
+        Unit::Minutes => "active minutes",
+        Unit::Steps => "steps",
+    };
+    let mut lines = Vec::new();
+
+    lines.push(format!(
+        "The day starts at waking up (at {} without sleep data) and lasts {} hours, but no later than {}.",
+        day.day_begins_at.format("%H:%M"),
+        day.day_length,
+        day.day_ends_at.format("%H:%M")
+    ));
+
+    // Hours of a day started at the configured time
+    let begins_at = chrono::NaiveDate::from_ymd(2019, 1, 7).and_time(day.day_begins_at);
+    let ends_at = if day.is_overnight() {
+        begins_at.date().succ().and_time(day.day_ends_at)
+    } else {
+        begins_at.date().and_time(day.day_ends_at)
+    };
+    let ends_at = std::cmp::min(begins_at + Duration::hours(day.day_length), ends_at);
+    let mut hours = Vec::new();
+    let mut time = begins_at;
+    while time < ends_at {
+        hours.push(time.hour());
+        time += Duration::hours(1);
+    }
+    let total = hours.iter().map(|&h| limits.requirement(h)).sum::<u32>();
+
+    lines.push(format!(
+        "Every hour requires {} {}; at most {} of them count per hour.",
+        limits.minimum_active_time, unit, limits.max_accounted_active_time
+    ));
+    for profile in &limits.profile {
+        lines.push(format!(
+            "Hours {:02}:00 to {:02}:00 require {} {} instead.",
+            profile.from, profile.to, profile.minimum_active_time, unit
+        ));
+    }
+    lines.push(format!(
+        "A typical day of {} tracked hours requires {} {} in total.",
+        hours.len(),
+        total,
+        unit
+    ));
+
+    lines.push(match limits.policy {
+        DebtPolicyKind::HourlyChain => {
+            let hours = if limits.minimum_active_time > 0 {
+                limits.debt_limit / limits.minimum_active_time
+            } else {
+                0
+            };
+            format!(
+                "Unpaid requirement is carried over to the next hours; debt grows up to {} {}, \
+                 i.e. {} inactive hours in a row.",
+                limits.debt_limit, unit, hours
+            )
+        }
+        DebtPolicyKind::RollingWindow { max_sedentary } => format!(
+            "Every {} minutes of sitting in a row add the hour's requirement to the debt, regardless of \
+             the hour boundaries; the activity after them pays it off, debt grows up to {} {}.",
+            max_sedentary, limits.debt_limit, unit
+        ),
+        DebtPolicyKind::DailyQuota { quota } => format!(
+            "{} {} are required over the day, {} per hour on average; surplus of the earlier hours covers \
+             the later ones, debt grows up to {} {}.",
+            quota,
+            unit,
+            quota / day.day_length.max(1) as u32,
+            limits.debt_limit,
+            unit
+        ),
+    });
+
+    if let Some(max_sedentary_time) = limits.max_sedentary_time {
+        lines.push(format!(
+            "Sitting for more than {} minutes in a row adds {} {} to the debt.",
+            max_sedentary_time, limits.minimum_active_time, unit
+        ));
+    }
+
+    lines
+}
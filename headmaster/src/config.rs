@@ -1,78 +1,566 @@
-use chrono::NaiveTime;
+use chrono::{NaiveTime, Weekday};
+use chrono_tz::Tz;
 use failure::{format_err, Error};
+use headmaster::{DetailedActivityStats, Metric, Unit, MAX_SEVERITY};
+use priestess::MetsThresholds;
 use serde::{Deserialize, Serialize};
-use std::fmt::Display;
+use std::collections::HashSet;
+use std::fmt::{self, Display};
 use std::fs::File;
 use std::io::Read;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub auth: Auth,
-    pub limits: Limits,
-    pub day: Day,
     pub network: Network,
+    pub storage: Storage,
+    pub users: Vec<User>,
+    #[serde(default)]
+    pub challenges: Vec<Challenge>,
 }
 
 impl Config {
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
-        let mut file = File::open(path)?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)?;
-        let config: Config = toml::from_str(&contents)?;
+        let (config, _) = Self::read(path)?;
+
+        let problems = config.problems();
+        if !problems.is_empty() {
+            let problems = problems.iter().map(ToString::to_string).collect::<Vec<_>>();
+            return Err(format_err!("invalid config:\n{}", problems.join("\n")));
+        }
+
+        Ok(config)
+    }
+
+    /// Parse the config without checking the invariants, returns it along with the layers it's read from.
+    /// The base file is overridden by the `conf.d/*.toml` files next to it in the name order,
+    /// then by the `DISCIPLINATOR_*` environment variables
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<(Self, Vec<Layer>), Error> {
+        let path = path.as_ref();
+        let contents = read_file(path)?;
+        let mut value = parse_layer(path, &contents)?;
+        let mut layers = vec![Layer::File {
+            path: path.to_owned(),
+            contents,
+        }];
+
+        for path in Self::layers(path)? {
+            let contents = read_file(&path)?;
+            merge(&mut value, parse_layer(&path, &contents)?);
+            layers.push(Layer::File { path, contents });
+        }
+
+        for (name, raw) in env_overrides() {
+            let field = apply_env_override(&mut value, &name, &raw)
+                .map_err(|e| format_err!("environment variable {}: {}", name, e))?;
+            layers.push(Layer::Env { name, field });
+        }
+
+        let config: Config = value.try_into()?;
+        Ok((config, layers))
+    }
+
+    /// Files the config at `path` is read from: the base file, the layers and the secrets
+    pub fn sources<P: AsRef<Path>>(path: P) -> Result<Vec<PathBuf>, Error> {
+        let path = path.as_ref();
+        let mut sources = vec![path.to_owned()];
+        sources.extend(Self::layers(path)?);
+        sources.extend(
+            env_overrides()
+                .into_iter()
+                .filter(|(name, _)| name.ends_with("_FILE"))
+                .map(|(_, path)| PathBuf::from(path)),
+        );
+        Ok(sources)
+    }
+
+    /// `conf.d/*.toml` files next to the base config at `path` in the name order
+    fn layers(path: &Path) -> Result<Vec<PathBuf>, Error> {
+        let conf_dir = path
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .join("conf.d");
+        if !conf_dir.is_dir() {
+            return Ok(vec![]);
+        }
+
+        let mut layers = std::fs::read_dir(&conf_dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        layers.retain(|path| path.extension().map_or(false, |ext| ext == "toml"));
+        layers.sort();
+        Ok(layers)
+    }
+
+    /// All the invariant violations of the config
+    pub fn problems(&self) -> Vec<Problem> {
+        let mut problems = Vec::new();
+        if self.users.is_empty() {
+            problems.push(Problem::new(
+                "users",
+                "at least one user should be configured",
+            ));
+        }
+
+        let mut ids = HashSet::new();
+        let mut api_keys = HashSet::new();
+        for user in &self.users {
+            if !ids.insert(user.id.as_str()) {
+                problems.push(Problem::new(
+                    format!("users.{}.id", user.id),
+                    format!("user id {:?} is not unique", user.id),
+                ));
+            }
+            // Requests are matched with the users by the key
+            if !api_keys.insert(user.api_key.as_str()) {
+                problems.push(Problem::new(
+                    format!("users.{}.api_key", user.id),
+                    "api key is shared with another user",
+                ));
+            }
+            Self::check_user(user, &mut problems);
+        }
 
-        // Check invariants
+        problems
+    }
+
+    fn check_user(user: &User, problems: &mut Vec<Problem>) {
+        // User id is a part of the url path and of the data directory path
+        let id_is_valid = !user.id.is_empty()
+            && user
+                .id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !id_is_valid {
+            problems.push(Problem::new(
+                format!("users.{}.id", user.id),
+                format!(
+                    "user id {:?} should be a non-empty string of alphanumerics, '_' and '-'",
+                    user.id
+                ),
+            ));
+        }
+
+        let prefix = format!("users.{}", user.id);
+        Self::check_day(&prefix, &user.day, problems);
+        Self::check_limits(&prefix, &user.limits, &user.day, problems);
         Self::check_field_ranges(
-            "limits.hourly_minimum_active_time",
-            config.limits.minimum_active_time,
-            5,
-            60,
-        )?;
+            &format!("{}.grace_period_minutes", prefix),
+            user.grace_period_minutes,
+            0,
+            59,
+            problems,
+        );
+        if user.severity_levels.len() as u32 >= MAX_SEVERITY {
+            problems.push(Problem::new(
+                format!("{}.severity_levels", prefix),
+                format!("at most {} levels can be configured", MAX_SEVERITY - 1),
+            ));
+        }
+        for (i, level) in user.severity_levels.iter().enumerate() {
+            // Debt never exceeds the limit, so the level would never be reached
+            let debt_limit = user.limits.debt_limit;
+            match level.debt {
+                Some(debt) if debt > debt_limit => problems.push(Problem::new(
+                    format!("{}.severity_levels[{}].debt", prefix, i),
+                    format!(
+                        "{} is never reached, the debt is capped by limits.debt_limit = {}",
+                        debt, debt_limit
+                    ),
+                )),
+                _ => (),
+            }
+            if level.debt.is_none() && level.collection_minutes.is_none() {
+                problems.push(Problem::new(
+                    format!("{}.severity_levels[{}]", prefix, i),
+                    "either debt or collection_minutes should be set",
+                ));
+            }
+        }
+        if let Some(mets) = &user.mets_thresholds {
+            let is_ascending = 0.0 < mets.lightly_active
+                && mets.lightly_active < mets.fairly_active
+                && mets.fairly_active < mets.heavy_active;
+            if !is_ascending {
+                problems.push(Problem::new(
+                    format!("{}.mets_thresholds", prefix),
+                    "thresholds should be positive and ascending: lightly_active < fairly_active < heavy_active",
+                ));
+            }
+        }
+        if let Some(decay) = &user.decay {
+            Self::check_field_ranges(
+                &format!("{}.decay.percent", prefix),
+                decay.percent,
+                1,
+                100,
+                problems,
+            );
+        }
+        if let Some(bank) = &user.bank {
+            let prefix = format!("{}.bank", prefix);
+            // Credit is in the unit of the limits
+            let scale = Self::unit_scale(user.limits.unit);
+            Self::check_field_ranges(
+                &format!("{}.cap", prefix),
+                bank.cap,
+                scale,
+                600 * scale,
+                problems,
+            );
+            Self::check_field_ranges(
+                &format!("{}.expiry_hours", prefix),
+                bank.expiry_hours,
+                1,
+                24,
+                problems,
+            );
+            Self::check_field_ranges(
+                &format!("{}.share_percent", prefix),
+                bank.share_percent,
+                1,
+                100,
+                problems,
+            );
+        }
+        // Overrides are checked for what they set only, the rest is checked with the base schedule
+        for (i, weekdays) in user.weekdays.iter().enumerate() {
+            let schedule = weekdays.apply(user.schedule_base());
+            let prefix = format!("{}.weekdays[{}]", prefix, i);
+            let day_overridden = weekdays.day_begins_at.is_some()
+                || weekdays.day_ends_at.is_some()
+                || weekdays.day_length.is_some();
+            if day_overridden {
+                Self::check_day(&prefix, &schedule.day, problems);
+            }
+            if weekdays.limits.is_some() {
+                Self::check_limits(&prefix, &schedule.limits, &schedule.day, problems);
+            }
+        }
+    }
+
+    fn check_limits(prefix: &str, limits: &Limits, day: &Day, problems: &mut Vec<Problem>) {
+        let scale = Self::unit_scale(limits.unit);
         Self::check_field_ranges(
-            "limits.hourly_max_accounted_active_time",
-            config.limits.max_accounted_active_time,
-            5,
-            60,
-        )?;
+            &format!("{}.limits.minimum_active_time", prefix),
+            limits.minimum_active_time,
+            5 * scale,
+            60 * scale,
+            problems,
+        );
+        for (i, profile) in limits.profile.iter().enumerate() {
+            let prefix = format!("{}.limits.profile[{}]", prefix, i);
+            Self::check_field_ranges(&format!("{}.from", prefix), profile.from, 0, 23, problems);
+            Self::check_field_ranges(
+                &format!("{}.to", prefix),
+                profile.to,
+                profile.from + 1,
+                24,
+                problems,
+            );
+            Self::check_field_ranges(
+                &format!("{}.minimum_active_time", prefix),
+                profile.minimum_active_time,
+                0,
+                60 * scale,
+                problems,
+            );
+            // Requirement above the accounted maximum can never be paid off
+            if profile.minimum_active_time > limits.max_accounted_active_time {
+                problems.push(Problem::new(
+                    format!("{}.minimum_active_time", prefix),
+                    format!(
+                        "{} is more than limits.max_accounted_active_time = {}, the hours could never be paid off",
+                        profile.minimum_active_time, limits.max_accounted_active_time
+                    ),
+                ));
+            }
+        }
         Self::check_field_ranges(
-            "limits.absolute_debt_limit",
-            config.limits.debt_limit,
-            5,
-            3600,
-        )?;
+            &format!("{}.limits.max_accounted_active_time", prefix),
+            limits.max_accounted_active_time,
+            5 * scale,
+            60 * scale,
+            problems,
+        );
         Self::check_field_ranges(
-            "day.day_begins_at",
-            config.day.day_begins_at,
-            NaiveTime::from_hms(0, 0, 0),
-            config.day.day_ends_at,
-        )?;
+            &format!("{}.limits.debt_limit", prefix),
+            limits.debt_limit,
+            5 * scale,
+            3600 * scale,
+            problems,
+        );
+        if limits.max_accounted_active_time < limits.minimum_active_time {
+            problems.push(Problem::new(
+                format!("{}.limits.max_accounted_active_time", prefix),
+                format!(
+                    "{} is less than limits.minimum_active_time = {}, the hours could never be paid off",
+                    limits.max_accounted_active_time, limits.minimum_active_time
+                ),
+            ));
+        }
+        // Debt is accumulated by the hourly minimums, a remainder would only be reached by a partly paid hour
+        if limits.minimum_active_time > 0 && limits.debt_limit % limits.minimum_active_time != 0 {
+            problems.push(Problem::new(
+                format!("{}.limits.debt_limit", prefix),
+                format!(
+                    "{} should be a multiple of limits.minimum_active_time = {}",
+                    limits.debt_limit, limits.minimum_active_time
+                ),
+            ));
+        }
+        let weights = &limits.weights;
+        let levels = [
+            ("lightly_active", weights.lightly_active),
+            ("fairly_active", weights.fairly_active),
+            ("heavy_active", weights.heavy_active),
+        ];
+        for (level, weight) in &levels {
+            Self::check_field_ranges(
+                &format!("{}.limits.weights.{}", prefix, level),
+                *weight,
+                0.0,
+                4.0,
+                problems,
+            );
+        }
+        if let Some(max_sedentary_time) = limits.max_sedentary_time {
+            Self::check_field_ranges(
+                &format!("{}.limits.max_sedentary_time", prefix),
+                max_sedentary_time,
+                10,
+                600,
+                problems,
+            );
+        }
+        if let DebtPolicyKind::RollingWindow { max_sedentary } = limits.policy {
+            Self::check_field_ranges(
+                &format!("{}.limits.policy.max_sedentary", prefix),
+                max_sedentary,
+                10,
+                600,
+                problems,
+            );
+        }
+        if let DebtPolicyKind::DailyQuota { quota } = limits.policy {
+            let field = format!("{}.limits.policy.quota", prefix);
+            Self::check_field_ranges(&field, quota, 5, 1440, problems);
+            // Every hour of the day accounts no more than the maximum
+            let reachable = limits.max_accounted_active_time * day.day_length.max(0) as u32;
+            if quota > reachable {
+                problems.push(Problem::new(
+                    field,
+                    format!(
+                        "{} is more than day.day_length * limits.max_accounted_active_time = {}, the quota could never be met",
+                        quota, reachable
+                    ),
+                ));
+            }
+        }
+    }
+
+    fn check_day(prefix: &str, day: &Day, problems: &mut Vec<Problem>) {
         Self::check_field_ranges(
-            "day.day_ends_at",
-            config.day.day_ends_at,
-            config.day.day_begins_at,
-            NaiveTime::from_hms(23, 59, 59),
-        )?;
+            &format!("{}.day.day_length", prefix),
+            day.day_length,
+            1,
+            24,
+            problems,
+        );
+        if day.day_begins_at == day.day_ends_at {
+            problems.push(Problem::new(
+                format!("{}.day.day_ends_at", prefix),
+                "day_begins_at and day_ends_at should differ",
+            ));
+        }
+    }
 
-        Ok(config)
+    /// Steps are counted by hundreds where minutes are counted by ones
+    fn unit_scale(unit: Unit) -> u32 {
+        match unit {
+            Unit::Minutes => 1,
+            Unit::Steps => 100,
+        }
     }
 
-    fn check_field_ranges<T: Ord + Display>(
+    fn check_field_ranges<T: PartialOrd + Display>(
         name: &str,
         field: T,
         lower: T,
         upper: T,
-    ) -> Result<(), Error> {
+        problems: &mut Vec<Problem>,
+    ) {
         if field < lower || field > upper {
-            Err(format_err!(
-                "value of the field {:?} = {} is out of range {} <= value <= {}",
+            problems.push(Problem::new(
                 name,
-                field,
-                lower,
-                upper
-            ))
-        } else {
-            Ok(())
+                format!(
+                    "value {} is out of range {} <= value <= {}",
+                    field, lower, upper
+                ),
+            ));
+        }
+    }
+}
+
+/// Source of the config values, the later layers override the earlier ones
+#[derive(Debug, Clone)]
+pub enum Layer {
+    File {
+        path: PathBuf,
+        contents: String,
+    },
+    /// `field` is the dotted path the variable `name` sets, e.g. `users.default.limits.debt_limit`
+    Env {
+        name: String,
+        field: String,
+    },
+}
+
+/// Prefix of the environment variables overriding the config values, e.g.
+/// `DISCIPLINATOR_USERS__DEFAULT__LIMITS__DEBT_LIMIT=20` sets `debt_limit` of the user `default`.
+/// Variables ending with `_FILE` read the value from the file, e.g. `DISCIPLINATOR_AUTH__CLIENT_SECRET_FILE`
+const ENV_PREFIX: &str = "DISCIPLINATOR_";
+
+/// `DISCIPLINATOR_*` variables in the name order
+fn env_overrides() -> Vec<(String, String)> {
+    let mut vars = std::env::vars()
+        .filter(|(name, _)| name.starts_with(ENV_PREFIX))
+        .collect::<Vec<_>>();
+    vars.sort();
+    vars
+}
+
+fn read_file(path: &Path) -> Result<String, Error> {
+    let mut file =
+        File::open(path).map_err(|e| format_err!("failed to open {}: {}", path.display(), e))?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+fn parse_layer(path: &Path, contents: &str) -> Result<toml::Value, Error> {
+    contents
+        .parse()
+        .map_err(|e| format_err!("invalid config {}: {}", path.display(), e))
+}
+
+/// Merge the `layer` into the `base`: tables are merged recursively, entries of the arrays of tables
+/// with ids (i.e. `[[users]]`) are merged by the id, anything else is replaced
+fn merge(base: &mut toml::Value, layer: toml::Value) {
+    let id = |value: &toml::Value| {
+        value
+            .get("id")
+            .and_then(toml::Value::as_str)
+            .map(str::to_owned)
+    };
+    match (base, layer) {
+        (toml::Value::Table(base), toml::Value::Table(layer)) => {
+            for (key, value) in layer {
+                match base.get_mut(&key) {
+                    Some(base) => merge(base, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (toml::Value::Array(base), toml::Value::Array(layer))
+            if !layer.is_empty() && layer.iter().all(|v| id(v).is_some()) =>
+        {
+            for value in layer {
+                match base.iter_mut().find(|b| id(b) == id(&value)) {
+                    Some(base) => merge(base, value),
+                    None => base.push(value),
+                }
+            }
         }
+        (base, layer) => *base = layer,
+    }
+}
+
+/// Set the value of the `DISCIPLINATOR_*` variable `name`, path segments are separated by `__`
+/// and the users are addressed by the id. Returns the dotted path of the value set
+fn apply_env_override(root: &mut toml::Value, name: &str, raw: &str) -> Result<String, Error> {
+    let (name, value) = if name.ends_with("_FILE") {
+        let path = Path::new(raw);
+        let secret = read_file(path)?;
+        let name = &name[..name.len() - "_FILE".len()];
+        (name, toml::Value::String(secret.trim_end().to_owned()))
+    } else {
+        // Numbers, booleans, arrays and quoted strings are parsed as TOML, anything else is a string
+        let value = format!("value = {}", raw)
+            .parse::<toml::Value>()
+            .ok()
+            .and_then(|table| table.get("value").cloned())
+            .unwrap_or_else(|| toml::Value::String(raw.to_owned()));
+        (name, value)
+    };
+
+    let path = name[ENV_PREFIX.len()..]
+        .split("__")
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>();
+    let (key, tables) = path
+        .split_last()
+        .ok_or_else(|| format_err!("empty config path"))?;
+
+    let mut current = root;
+    for segment in tables {
+        current = match current {
+            toml::Value::Table(table) => table
+                .entry(segment.clone())
+                .or_insert_with(|| toml::Value::Table(Default::default())),
+            toml::Value::Array(array) => array
+                .iter_mut()
+                .find(|entry| {
+                    entry
+                        .get("id")
+                        .and_then(toml::Value::as_str)
+                        .map_or(false, |id| id.eq_ignore_ascii_case(segment))
+                })
+                .ok_or_else(|| format_err!("no entry with id {:?}", segment))?,
+            _ => return Err(format_err!("{:?} is not a table", segment)),
+        };
+    }
+
+    let table = current
+        .as_table_mut()
+        .ok_or_else(|| format_err!("{:?} is not a table", key))?;
+    // Typed value is kept only where it fits: string values stay strings (e.g. `API_KEY=12345`),
+    // and times are strings in the config (e.g. `DAY_BEGINS_AT=10:00:00`)
+    let value = match value {
+        toml::Value::Datetime(_) => toml::Value::String(raw.to_owned()),
+        toml::Value::String(_) => value,
+        _ if table.get(key).map_or(false, toml::Value::is_str) => {
+            toml::Value::String(raw.to_owned())
+        }
+        _ => value,
+    };
+    table.insert(key.clone(), value);
+    Ok(path.join("."))
+}
+
+/// Invariant violation of the config
+#[derive(Debug, Clone)]
+pub struct Problem {
+    /// dotted path to the offending value, e.g. `users.default.limits.debt_limit`
+    pub field: String,
+    pub message: String,
+}
+
+impl Problem {
+    fn new<F: Into<String>, M: Into<String>>(field: F, message: M) -> Self {
+        Problem {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
     }
 }
 
@@ -82,24 +570,466 @@ pub struct Auth {
     pub client_secret: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct User {
+    /// unique id, used in the API urls: /api/v1/users/{id}/...
+    pub id: String,
+    /// key the user's requests should be authorized with (`Authorization: Bearer <api_key>`)
+    pub api_key: String,
+    /// user's own FitBit application credentials, global ones are used if not set
+    pub auth: Option<Auth>,
+    /// IANA timezone name, the one from the FitBit profile is used if not set
+    pub timezone: Option<Tz>,
+    /// classify the minutes by their METs, FitBit's activity levels are used if not set
+    pub mets_thresholds: Option<MetsThresholds>,
+    pub limits: Limits,
+    pub day: Day,
+    /// day window and limits overrides for the selected weekdays, later entries take precedence
+    #[serde(default)]
+    pub weekdays: Vec<WeekdaysOverride>,
+    /// iCalendar files with days off or busy time
+    #[serde(default)]
+    pub calendars: Vec<CalendarSource>,
+    /// manual pauses of the debt collection
+    #[serde(default)]
+    pub pause: PauseQuota,
+    /// aggregates shared with the team, nothing is shared by default
+    #[serde(default)]
+    pub sharing: Sharing,
+    /// minutes after the hour boundary to start paying a new debt off before it's collected
+    #[serde(default)]
+    pub grace_period_minutes: i64,
+    /// thresholds escalating the debt collection severity to 2, 3 and so on, it's 1 otherwise
+    #[serde(default)]
+    pub severity_levels: Vec<SeverityLevel>,
+    /// overtime credit, disabled if not set
+    pub bank: Option<Bank>,
+    /// forgiveness of the outstanding debt, debt is never forgiven if not set
+    pub decay: Option<Decay>,
+}
+
+impl User {
+    /// Day window and limits effective on the `weekday`
+    pub fn schedule(&self, weekday: Weekday) -> Schedule {
+        self.weekdays
+            .iter()
+            .filter(|weekdays| weekdays.days.contains(&weekday))
+            .fold(self.schedule_base(), |schedule, weekdays| {
+                weekdays.apply(schedule)
+            })
+    }
+
+    fn schedule_base(&self) -> Schedule {
+        Schedule {
+            day: self.day.clone(),
+            limits: self.limits.clone(),
+            tracking_disabled: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    pub day: Day,
+    pub limits: Limits,
+    /// no debt is collected for the whole day
+    pub tracking_disabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeekdaysOverride {
+    pub days: Vec<Weekday>,
+    #[serde(default)]
+    pub tracking_disabled: bool,
+    pub day_begins_at: Option<NaiveTime>,
+    pub day_ends_at: Option<NaiveTime>,
+    pub day_length: Option<i64>,
+    pub limits: Option<Limits>,
+}
+
+impl WeekdaysOverride {
+    fn apply(&self, mut schedule: Schedule) -> Schedule {
+        let day = &mut schedule.day;
+        day.day_begins_at = self.day_begins_at.unwrap_or(day.day_begins_at);
+        day.day_ends_at = self.day_ends_at.unwrap_or(day.day_ends_at);
+        day.day_length = self.day_length.unwrap_or(day.day_length);
+        if let Some(limits) = &self.limits {
+            schedule.limits = limits.clone();
+        }
+        schedule.tracking_disabled |= self.tracking_disabled;
+        schedule
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CalendarSource {
+    /// path to the .ics file, the file is re-read once modified
+    pub path: PathBuf,
+    #[serde(default)]
+    pub kind: CalendarKind,
+    /// busy hours requirement is moved into the next free hour instead of being waived
+    #[serde(default)]
+    pub shift_requirement: bool,
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CalendarKind {
+    /// events tagged as holiday, vacation or sick leave
+    DaysOff,
+    /// all the events blocking the time, e.g. meetings
+    Busy,
+}
+
+impl Default for CalendarKind {
+    fn default() -> Self {
+        CalendarKind::DaysOff
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PauseQuota {
+    /// hours of pause available per ISO week, pauses are not allowed if not set
+    #[serde(default)]
+    pub weekly_quota_hours: u32,
+}
+
+/// Overtime above `max_accounted_active_time` is credited and prepays the later hours of the day window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bank {
+    /// maximum credit in the unit of the limits, overtime above it is lost
+    pub cap: u32,
+    /// hours the credit is available for
+    pub expiry_hours: i64,
+    /// share of an hour's requirement the credit may prepay, in percents
+    pub share_percent: u32,
+}
+
+/// Severity level is reached by the debt size or by the time the debt is collected for, whichever comes first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeverityLevel {
+    pub debt: Option<u32>,
+    pub collection_minutes: Option<i64>,
+}
+
+impl SeverityLevel {
+    pub fn is_reached(&self, debt: u32, collection_minutes: i64) -> bool {
+        self.debt.map_or(false, |level| debt >= level)
+            || self
+                .collection_minutes
+                .map_or(false, |level| collection_minutes >= level)
+    }
+}
+
+/// Outstanding debt is partly forgiven after every complete hour with its own requirement fulfilled
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Decay {
+    /// share of the outstanding debt forgiven, in percents
+    pub percent: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Sharing {
+    #[serde(default)]
+    pub debt_free_hours: bool,
+    #[serde(default)]
+    pub active_minutes: bool,
+    #[serde(default)]
+    pub streaks: bool,
+}
+
+impl Sharing {
+    pub fn is_shared(&self, metric: Metric) -> bool {
+        match metric {
+            Metric::ActiveMinutes => self.active_minutes,
+            Metric::DebtFreeHours => self.debt_free_hours,
+        }
+    }
+}
+
+/// Team goal collectively reached by the members sharing the metric
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Challenge {
+    pub name: String,
+    pub metric: Metric,
+    pub goal: u32,
+    /// challenge runs weekly from Monday till the end of this day
+    pub deadline: Weekday,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Limits {
+    /// unit of the requirements and of the debt: active minutes or steps
+    #[serde(default)]
+    pub unit: Unit,
     pub minimum_active_time: u32,
+    /// `minimum_active_time` overrides for the hours of the day, later entries take precedence
+    #[serde(default)]
+    pub profile: Vec<HourlyRequirement>,
     pub max_accounted_active_time: u32,
     pub debt_limit: u32,
+    /// maximum continuous sedentary time in minutes regardless of the hour boundaries, not limited if not set
+    pub max_sedentary_time: Option<u32>,
+    /// rule the debt is calculated with
+    #[serde(default)]
+    pub policy: DebtPolicyKind,
+    /// how much a minute of each activity level counts for the debt payment
+    #[serde(default)]
+    pub weights: Weights,
+}
+
+impl Limits {
+    /// Requirement of the `hour` of the day
+    pub fn requirement(&self, hour: u32) -> u32 {
+        self.profile
+            .iter()
+            .rev()
+            .find(|profile| profile.from <= hour && hour < profile.to)
+            .map(|profile| profile.minimum_active_time)
+            .unwrap_or(self.minimum_active_time)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HourlyRequirement {
+    /// first hour of the range
+    pub from: u32,
+    /// hour the range ends before
+    pub to: u32,
+    pub minimum_active_time: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Weights {
+    pub lightly_active: f32,
+    pub fairly_active: f32,
+    pub heavy_active: f32,
+}
+
+impl Weights {
+    /// Active minutes weighted by the intensity level
+    pub fn weigh(&self, detailed: &DetailedActivityStats) -> u32 {
+        let score = detailed.lightly_active as f32 * self.lightly_active
+            + detailed.fairly_active as f32 * self.fairly_active
+            + detailed.heavy_active as f32 * self.heavy_active;
+        score.round() as u32
+    }
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Weights {
+            lightly_active: 1.0,
+            fairly_active: 1.0,
+            heavy_active: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DebtPolicyKind {
+    /// every hour requires `minimum_active_time`, unpaid debt is carried over to the next hours
+    HourlyChain,
+    /// no more than `max_sedentary` minutes of sitting in a row, each time it's exceeded the hour's requirement is added
+    RollingWindow { max_sedentary: u32 },
+    /// `quota` minutes over the day, spread evenly over the `day_length` hours
+    DailyQuota { quota: u32 },
+}
+
+impl Default for DebtPolicyKind {
+    fn default() -> Self {
+        DebtPolicyKind::HourlyChain
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Day {
     /// used when there's no sleep data
     pub day_begins_at: NaiveTime,
-    /// used regardless of sleep data: there should be some time for leisure in the evening
+    /// used regardless of sleep data: there should be some time for leisure in the evening.
+    /// Day window crosses midnight if it's earlier than `day_begins_at`
     pub day_ends_at: NaiveTime,
     /// day length, used if sleep data is available (in hours)
     pub day_length: i64,
 }
 
+impl Day {
+    /// Day window crosses midnight and ends on the next date
+    pub fn is_overnight(&self) -> bool {
+        self.day_begins_at > self.day_ends_at
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Network {
     pub addr: String,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Storage {
+    /// directory for the per-user data, each user gets a `{data_dir}/{id}` subdirectory
+    pub data_dir: PathBuf,
+}
+
+impl Storage {
+    pub fn user_dir(&self, id: &str) -> PathBuf {
+        self.data_dir.join(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(toml: &str) -> toml::Value {
+        toml.parse().unwrap()
+    }
+
+    fn base() -> toml::Value {
+        value(
+            r#"
+            [network]
+            addr = "127.0.0.1:8080"
+            port_reuse = true
+
+            [[users]]
+            id = "default"
+            api_key = "secret"
+            [users.day]
+            day_begins_at = "09:00:00"
+            [users.limits]
+            debt_limit = 15
+            weekly_days_off = ["Sat", "Sun"]
+
+            [[users]]
+            id = "other"
+            api_key = "other"
+            "#,
+        )
+    }
+
+    fn user<'a>(root: &'a toml::Value, id: &str) -> &'a toml::Value {
+        root["users"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|u| u["id"].as_str() == Some(id))
+            .unwrap()
+    }
+
+    #[test]
+    fn merge_tables_recursively() {
+        let mut root = base();
+        merge(&mut root, value("[network]\naddr = \"0.0.0.0:8081\""));
+        assert_eq!(root["network"]["addr"].as_str(), Some("0.0.0.0:8081"));
+        assert_eq!(root["network"]["port_reuse"].as_bool(), Some(true));
+    }
+
+    #[test]
+    fn merge_users_by_id() {
+        let mut root = base();
+        merge(
+            &mut root,
+            value(
+                r#"
+                [[users]]
+                id = "other"
+                api_key = "changed"
+
+                [[users]]
+                id = "new"
+                api_key = "new"
+                "#,
+            ),
+        );
+        assert_eq!(root["users"].as_array().unwrap().len(), 3);
+        assert_eq!(user(&root, "default")["api_key"].as_str(), Some("secret"));
+        assert_eq!(user(&root, "other")["api_key"].as_str(), Some("changed"));
+        assert_eq!(user(&root, "new")["api_key"].as_str(), Some("new"));
+    }
+
+    #[test]
+    fn merge_replaces_plain_arrays() {
+        let mut root = base();
+        merge(
+            &mut root,
+            value("[[users]]\nid = \"default\"\n[users.limits]\nweekly_days_off = [\"Fri\"]"),
+        );
+        let days = &user(&root, "default")["limits"]["weekly_days_off"];
+        assert_eq!(days.as_array().unwrap().len(), 1);
+        assert_eq!(
+            user(&root, "default")["limits"]["debt_limit"].as_integer(),
+            Some(15)
+        );
+    }
+
+    #[test]
+    fn env_override_sets_typed_values_by_user_id() {
+        let mut root = base();
+        let field = apply_env_override(
+            &mut root,
+            "DISCIPLINATOR_USERS__DEFAULT__LIMITS__DEBT_LIMIT",
+            "20",
+        )
+        .unwrap();
+        assert_eq!(field, "users.default.limits.debt_limit");
+        assert_eq!(
+            user(&root, "default")["limits"]["debt_limit"].as_integer(),
+            Some(20)
+        );
+    }
+
+    #[test]
+    fn env_override_keeps_strings_and_times_as_strings() {
+        let mut root = base();
+        apply_env_override(&mut root, "DISCIPLINATOR_USERS__OTHER__API_KEY", "12345").unwrap();
+        apply_env_override(
+            &mut root,
+            "DISCIPLINATOR_USERS__OTHER__DAY__DAY_BEGINS_AT",
+            "10:00:00",
+        )
+        .unwrap();
+        apply_env_override(&mut root, "DISCIPLINATOR_NETWORK__ADDR", "0.0.0.0:8081").unwrap();
+        assert_eq!(user(&root, "other")["api_key"].as_str(), Some("12345"));
+        assert_eq!(
+            user(&root, "other")["day"]["day_begins_at"].as_str(),
+            Some("10:00:00")
+        );
+        assert_eq!(root["network"]["addr"].as_str(), Some("0.0.0.0:8081"));
+    }
+
+    #[test]
+    fn env_override_quoted_value_is_a_string() {
+        // Value is not set in the base, so only the quotes tell it's a string
+        let mut root = base();
+        apply_env_override(&mut root, "DISCIPLINATOR_STORAGE__NAME", "42").unwrap();
+        assert_eq!(root["storage"]["name"].as_integer(), Some(42));
+        apply_env_override(&mut root, "DISCIPLINATOR_STORAGE__NAME", "\"42\"").unwrap();
+        assert_eq!(root["storage"]["name"].as_str(), Some("42"));
+    }
+
+    #[test]
+    fn env_override_reads_files() {
+        let path = std::env::temp_dir().join("headmaster-config-test-secret");
+        std::fs::write(&path, "top secret\n").unwrap();
+        let mut root = base();
+        let field = apply_env_override(
+            &mut root,
+            "DISCIPLINATOR_AUTH__CLIENT_SECRET_FILE",
+            path.to_str().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(field, "auth.client_secret");
+        assert_eq!(root["auth"]["client_secret"].as_str(), Some("top secret"));
+    }
+
+    #[test]
+    fn env_override_fails_for_unknown_user() {
+        let mut root = base();
+        assert!(
+            apply_env_override(&mut root, "DISCIPLINATOR_USERS__NOBODY__API_KEY", "key").is_err()
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use crate::bank::CreditBank;
+use crate::config::{Bank, Limits, Schedule, User};
+use crate::Hour;
+use chrono::NaiveDateTime;
+
+/// Debt of the normalized `hours`: the overtime credit prepays the requirement, the debt policy is applied
+/// and the good hours forgive a share of the debt. Returns the credit left by `now`, if the bank is enabled
+pub fn calculate(
+    hours: Vec<Hour>,
+    schedule: &Schedule,
+    user: &User,
+    now: NaiveDateTime,
+) -> (Vec<Hour>, Option<u32>) {
+    let (mut hours, bank_balance) = spend_credit(hours, &schedule.limits, user.bank.as_ref(), now);
+
+    let policy = schedule.limits.policy.policy(&schedule.day);
+    policy.calculate(&mut hours, &schedule.limits);
+
+    if let Some(decay) = &user.decay {
+        forgive_debt(&mut hours, decay.percent);
+    }
+
+    (hours, bank_balance)
+}
+
+/// Bank the overtime discarded by the threshold and prepay the later hours' requirement with it.
+/// Returns the credit left by `now`, if the bank is enabled
+fn spend_credit(
+    mut hours: Vec<Hour>,
+    limits: &Limits,
+    bank: Option<&Bank>,
+    now: NaiveDateTime,
+) -> (Vec<Hour>, Option<u32>) {
+    let mut bank = match bank {
+        Some(config) => CreditBank::new(config.clone()),
+        None => return (hours, None),
+    };
+
+    for hour in hours.iter_mut().filter(|h| !h.tracking_disabled) {
+        if hour.complete {
+            let requirement = hour.requirement();
+            let needed = requirement
+                .checked_sub(hour.accounted_active_minutes)
+                .unwrap_or(0);
+            hour.prepaid_minutes = bank.withdraw(hour.start(), requirement, needed);
+            hour.accounted_active_minutes += hour.prepaid_minutes;
+        }
+
+        let overtime = hour
+            .activity()
+            .checked_sub(limits.max_accounted_active_time)
+            .unwrap_or(0);
+        bank.deposit(hour.start(), overtime);
+    }
+
+    (hours, Some(bank.balance(now)))
+}
+
+/// Forgive the `percent` of the outstanding debt after every good hour
+fn forgive_debt(hours: &mut [Hour], percent: u32) {
+    // Debt forgiven so far, it is subtracted from the debt the policy calculated
+    let mut forgiven = 0;
+    for hour in hours {
+        forgiven = u32::min(forgiven, hour.debt);
+        hour.debt -= forgiven;
+
+        let requirement = hour.requirement();
+        let is_good = hour.complete
+            && !hour.tracking_disabled
+            && hour.activity() + hour.prepaid_minutes >= requirement;
+        if is_good {
+            hour.forgiven_debt = hour.debt * percent / 100;
+            hour.debt -= hour.forgiven_debt;
+            forgiven += hour.forgiven_debt;
+        }
+    }
+}
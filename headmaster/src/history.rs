@@ -0,0 +1,87 @@
+use chrono::{Duration, NaiveDate};
+use failure::Error;
+use headmaster::{HourSummary, Settlement};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const DAYS_FILE: &str = "history.json";
+const SETTLEMENTS_FILE: &str = "settlements.json";
+/// Day logs are kept for two weeks, older ones are dropped. The rankings and the challenges read the last week only,
+/// but the days left unsettled are back-filled up to this many days back, recording their logs as well
+pub const RETENTION_DAYS: i64 = 14;
+
+/// Persistent log of the user's hourly summaries, one day log per date,
+/// and of the debt left unpaid at the end of the days
+pub struct History {
+    dir: PathBuf,
+    days: BTreeMap<NaiveDate, Vec<HourSummary>>,
+    settlements: BTreeMap<NaiveDate, Settlement>,
+}
+
+impl History {
+    /// Load the history from the `dir`, a new history is started if there are no files yet
+    pub fn load<P: AsRef<Path>>(dir: P) -> Result<Self, Error> {
+        let dir = dir.as_ref().to_owned();
+        Ok(History {
+            days: load_json(&dir.join(DAYS_FILE))?,
+            settlements: load_json(&dir.join(SETTLEMENTS_FILE))?,
+            dir,
+        })
+    }
+
+    /// Replace the day log of the `date` and persist the history, day logs older than the retention period are dropped
+    pub fn record(&mut self, date: NaiveDate, day_log: &[HourSummary]) -> Result<(), Error> {
+        // Summaries are requested more often than the hours change
+        if self.days.get(&date).map(Vec::as_slice) == Some(day_log) {
+            return Ok(());
+        }
+
+        self.days.insert(date, day_log.to_vec());
+        let oldest = date - Duration::days(RETENTION_DAYS);
+        self.days = self.days.split_off(&oldest);
+        save_json(&self.dir.join(DAYS_FILE), &self.days)
+    }
+
+    /// Latest day with the outcome recorded
+    pub fn last_settled(&self) -> Option<NaiveDate> {
+        self.settlements.keys().next_back().cloned()
+    }
+
+    /// Record the outcome of the day and persist it
+    pub fn settle(&mut self, settlement: Settlement) -> Result<(), Error> {
+        self.settlements.insert(settlement.date, settlement);
+        save_json(&self.dir.join(SETTLEMENTS_FILE), &self.settlements)
+    }
+
+    /// Day logs of the dates in the `from..=to` range
+    pub fn range(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> impl Iterator<Item = (&NaiveDate, &Vec<HourSummary>)> {
+        self.days.range(from..=to)
+    }
+}
+
+fn load_json<T: DeserializeOwned + Default>(path: &Path) -> Result<T, Error> {
+    if path.exists() {
+        Ok(serde_json::from_reader(File::open(path)?)?)
+    } else {
+        Ok(T::default())
+    }
+}
+
+/// Write the file atomically: a crash mid-write leaves the previous contents intact
+pub fn save_json<T: Serialize>(path: &Path, value: &T) -> Result<(), Error> {
+    let json = serde_json::to_string(value)?;
+    let tmp_path = path.with_extension("json.tmp");
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(json.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
@@ -1,29 +1,144 @@
+use chrono::{NaiveDate, NaiveDateTime};
 use serde::{Deserialize, Serialize};
 
+pub use priestess::DetailedActivityStats;
+
 #[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Summary {
     pub state: State,
     pub day_log: Vec<HourSummary>,
+    /// manual pause, if one is active
+    pub pause: Option<Pause>,
+    /// overtime credit available, if the bank is enabled
+    pub bank_balance: Option<u32>,
+    /// unit the debt and the requirements are denominated in
+    #[serde(default)]
+    pub unit: Unit,
+    /// debt collection begins at this time, if the debt is in the grace period
+    pub deadline: Option<NaiveDateTime>,
+    /// what's needed to avoid more debt and to clear the current one, if the current hour is tracked
+    pub plan: Option<Plan>,
+    /// escalation level of the debt collection from 1 to `MAX_SEVERITY`,
+    /// 0 if no debt is collected, including the paused collection
+    #[serde(default)]
+    pub severity: u32,
+}
+
+/// Projection of the debt by the current hour's data
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Plan {
+    /// activity required before the current hour ends not to get more debt, none if it can't be avoided
+    pub required_this_hour: Option<u32>,
+    /// latest time to start moving to be active for `required_this_hour` minutes in time
+    pub start_moving_by: Option<NaiveDateTime>,
+    /// time the debt is cleared at the current hour's pace, none if it's not cleared before the day ends
+    pub cleared_at: Option<NaiveDateTime>,
+}
+
+/// Highest severity level of the debt collection
+pub const MAX_SEVERITY: u32 = 5;
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Pause {
+    pub reason: String,
+    pub since: NaiveDateTime,
+    pub until: NaiveDateTime,
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct HourSummary {
+    pub date: NaiveDate,
     pub hour: u32,
     pub debt: u32,
+    /// requirement of the hour including the one shifted from the busy hours
+    #[serde(default)]
+    pub required_minutes: u32,
+    /// debt was capped by the debt limit
+    #[serde(default)]
+    pub debt_truncated: bool,
     pub active_minutes: u32,
+    /// active minutes weighted by the intensity, these pay the debt off
+    #[serde(default)]
+    pub weighted_active_minutes: u32,
+    /// active minutes by the intensity level
+    pub detailed: Option<DetailedActivityStats>,
+    /// steps made, if the goal is set in steps
+    pub steps: Option<u32>,
+    /// unit the debt is denominated in
+    #[serde(default)]
+    pub unit: Unit,
     pub tracking_disabled: bool,
+    pub tracking_disabled_reason: Option<TrackingDisabledReason>,
+    /// a continuous sedentary stretch exceeded the limit during the hour
+    #[serde(default)]
+    pub sedentary_limit_exceeded: bool,
     pub complete: bool,
 }
 
+impl HourSummary {
+    /// Activity paying the debt off, in the debt unit
+    pub fn activity(&self) -> u32 {
+        match self.unit {
+            Unit::Minutes => self.weighted_active_minutes,
+            Unit::Steps => self.steps.unwrap_or(0),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Unit {
+    /// active minutes, weighted by the intensity level
+    Minutes,
+    Steps,
+}
+
+impl Default for Unit {
+    fn default() -> Self {
+        Unit::Minutes
+    }
+}
+
+/// Outcome of a finished day window
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Settlement {
+    pub date: NaiveDate,
+    /// debt left when the day window ended
+    pub unpaid_debt: u32,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TrackingDisabledReason {
+    Sleep,
+    /// before the day begins or after it ends
+    OutsideDay,
+    /// tracking is disabled for the weekday
+    DayOff,
+    Holiday,
+    Vacation,
+    SickLeave,
+    Meeting,
+    /// debt collection is paused manually
+    Paused,
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 #[serde(tag = "type")]
 pub enum State {
     Normal(HourSummary),
+    /// there's a debt, but the grace period is not over yet
+    DebtWarning(HourSummary),
     DebtCollection(HourSummary),
     DebtCollectionPaused(HourSummary),
+    /// debt collection is paused manually
+    Paused(HourSummary),
 }
 
 impl State {
@@ -34,3 +149,42 @@ impl State {
         }
     }
 }
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    ActiveMinutes,
+    DebtFreeHours,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Leaderboard {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    /// complete hours passed without debt
+    pub debt_free_hours: Vec<Rank>,
+    pub active_minutes: Vec<Rank>,
+    /// longest run of debt-free hours, tracking disabled hours do not break the run
+    pub streaks: Vec<Rank>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Rank {
+    pub user: String,
+    pub value: u32,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChallengeStatus {
+    pub name: String,
+    pub metric: Metric,
+    pub goal: u32,
+    pub progress: u32,
+    pub from: NaiveDate,
+    pub deadline: NaiveDate,
+    pub complete: bool,
+    pub contributions: Vec<Rank>,
+}
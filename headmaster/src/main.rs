@@ -1,15 +1,38 @@
-use chrono::{DateTime, Local, NaiveDate, NaiveTime, Timelike};
-use failure::Error;
-use log::{debug, error, info};
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use failure::{format_err, Error};
+use log::{debug, error, info, warn};
 use tiny_http::{Method, Request, Response, Server};
 
-use headmaster::{HourSummary, State, Summary};
+use headmaster::{
+    DetailedActivityStats, HourSummary, Pause, Plan, Settlement, State, Summary,
+    TrackingDisabledReason, Unit,
+};
 use priestess::{
-    ActivityGrabber, FitbitActivityGrabber, FitbitAuthData, FitbitToken, SleepInterval, TokenStore,
+    ActivityGrabber, FitbitActivityGrabber, FitbitAuthData, FitbitToken, TokenRequired, TokenStore,
 };
 
+mod bank;
+mod calendar;
+mod check;
 mod config;
-use crate::config::Config;
+mod debt;
+mod history;
+mod pause;
+mod policy;
+mod projection;
+mod reload;
+mod simulate;
+mod team;
+#[cfg(test)]
+mod testing;
+use crate::calendar::Calendar;
+use crate::config::{Auth, Config, Limits, Schedule, User};
+use crate::history::{History, RETENTION_DAYS};
+use crate::pause::Pauses;
+use crate::reload::ConfigWatcher;
+use serde::Deserialize;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use structopt::StructOpt;
 
@@ -27,15 +50,35 @@ struct Options {
         parse(from_os_str)
     )]
     pub config_path: PathBuf,
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
 
-    /// Token path
-    #[structopt(
-        short = "t",
-        long = "token",
-        default_value = "./.fitbit_token",
-        parse(from_os_str)
-    )]
-    pub token_path: PathBuf,
+#[derive(Clone, Debug, StructOpt)]
+enum Command {
+    /// Report all the config problems at once and explain the configured rules
+    #[structopt(name = "check-config")]
+    CheckConfig,
+    /// Run the debt rules over a recorded or synthetic day and print the hourly calculation
+    #[structopt(name = "simulate")]
+    Simulate {
+        /// User to take the rules of, the first configured one by default
+        #[structopt(short = "u", long = "user")]
+        user: Option<String>,
+        /// JSON file with the hourly activity and sleep of the day
+        #[structopt(long = "fixture", parse(from_os_str), conflicts_with = "pattern")]
+        fixture: Option<PathBuf>,
+        /// Comma-separated active minutes per hour since the day start, repeated, e.g. "0,5,20"
+        #[structopt(long = "pattern", required_unless = "fixture")]
+        pattern: Option<String>,
+        /// Local time to simulate at, e.g. 2019-01-07T15:20:00; defaults to the fixture's or the current time
+        #[structopt(long = "now")]
+        now: Option<NaiveDateTime>,
+        /// Timezone of the simulated day, e.g. Europe/Moscow; defaults to the user's configured one, or UTC
+        /// if there's none, as the FitBit profile is not read during the simulation
+        #[structopt(long = "timezone")]
+        timezone: Option<Tz>,
+    },
 }
 
 fn main() -> Result<(), Error> {
@@ -44,36 +87,183 @@ fn main() -> Result<(), Error> {
     // Load args
     let options = Options::from_args();
 
-    // Load config
-    let config = Config::load(&options.config_path)?;
+    match options.command {
+        // Config is checked as a whole instead of failing on the first problem
+        Some(Command::CheckConfig) => check::run(&options.config_path),
+        Some(Command::Simulate {
+            user,
+            fixture,
+            pattern,
+            now,
+            timezone,
+        }) => {
+            let config = Config::load(&options.config_path)?;
+            let source = match (fixture, pattern) {
+                (Some(path), _) => simulate::Source::Fixture(path),
+                (None, Some(pattern)) => simulate::Source::Pattern(pattern),
+                (None, None) => return Err(format_err!("either fixture or pattern is required")),
+            };
+            simulate::run(&config, user, source, now, timezone)
+        }
+        None => serve(&options.config_path, Config::load(&options.config_path)?),
+    }
+}
 
+/// How often the config files are checked for modifications
+const CONFIG_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+fn serve(config_path: &Path, mut config: Config) -> Result<(), Error> {
     // Spin up the http server
     let server = Server::http(&config.network.addr)
         .map_err(|e| panic!("failed to startup the http server: {}", e))
         .unwrap();
 
-    // Create a headmaster instance containing the main debt computation logic
-    let mut master = Headmaster::new(config, options.clone());
+    // Create a headmaster instance per user, containing the main debt computation logic
+    let mut masters = HashMap::new();
+    for user in &config.users {
+        let auth = user.auth.clone().unwrap_or_else(|| config.auth.clone());
+        let data_dir = config.storage.user_dir(&user.id);
+        let master = Headmaster::new(auth, user.clone(), data_dir)?;
+        masters.insert(user.id.clone(), master);
+    }
+
+    let mut watcher = ConfigWatcher::new(config_path.to_owned());
+    loop {
+        // Config is swapped between the requests only
+        if let Some(reloaded) = watcher.poll() {
+            if let Err(e) = apply_config(&mut config, reloaded, &mut masters) {
+                error!("config reload rejected, keeping the previous one: {}", e);
+            }
+        }
+
+        let mut request = match server.recv_timeout(CONFIG_POLL_INTERVAL) {
+            Ok(Some(request)) => request,
+            Ok(None) => continue,
+            Err(_) => break,
+        };
 
-    for mut request in server.incoming_requests() {
         let mut serve = |request: &mut Request| -> Result<_, Error> {
-            // Token update procedure
-            if request.url().ends_with("update_token") && *request.method() == Method::Post {
-                let token: FitbitToken = serde_json::from_reader(request.as_reader())?;
-                token.save(&options.token_path)?;
-                Ok(Response::from_string("Token updated").with_status_code(200))
-            } else if request.url().ends_with("health") && *request.method() == Method::Get {
-                Ok(Response::from_string("Running").with_status_code(200))
-            } else if *request.method() == Method::Get {
-                let summary = master.current_summary()?;
-                Ok(Response::from_string(serde_json::to_string(&summary)?).with_status_code(200))
-            } else {
-                Ok(Response::from_string("Not found").with_status_code(404))
+            let url = request.url().to_owned();
+            let path = url.split('?').next().unwrap_or_default();
+            let segments = path
+                .split('/')
+                .filter(|s| !s.is_empty())
+                .collect::<Vec<_>>();
+
+            match segments.as_slice() {
+                ["health"] if *request.method() == Method::Get => {
+                    Ok(Response::from_string("Running").with_status_code(200))
+                }
+                ["api", "v1", "team", view] if *request.method() == Method::Get => {
+                    // Team view is available to the team members only
+                    let requester = masters
+                        .values_mut()
+                        .find(|master| is_authorized(request, &master.user.api_key));
+                    let today = match requester {
+                        Some(requester) => requester.current_time()?.date(),
+                        None => {
+                            return Ok(Response::from_string("Unauthorized").with_status_code(401))
+                        }
+                    };
+
+                    let members = masters
+                        .values()
+                        .map(|master| (&master.user, &master.history))
+                        .collect::<Vec<_>>();
+
+                    let json = match *view {
+                        "leaderboard" => {
+                            serde_json::to_string(&team::leaderboard(&members, today))?
+                        }
+                        "challenges" => serde_json::to_string(&team::challenges(
+                            &config.challenges,
+                            &members,
+                            today,
+                        ))?,
+                        _ => return Ok(Response::from_string("Not found").with_status_code(404)),
+                    };
+                    Ok(Response::from_string(json).with_status_code(200))
+                }
+                ["api", "v1", "users", id, action] => {
+                    let master = match masters.get_mut(*id) {
+                        Some(master) => master,
+                        None => return Ok(Response::from_string("Not found").with_status_code(404)),
+                    };
+
+                    // Users are isolated from each other: each one may only access own data
+                    if !is_authorized(request, &master.user.api_key) {
+                        return Ok(Response::from_string("Unauthorized").with_status_code(401));
+                    }
+
+                    match (request.method(), *action) {
+                        // Token update procedure
+                        (Method::Post, "update_token") => {
+                            let token: FitbitToken = serde_json::from_reader(request.as_reader())?;
+                            token.save(&master.token_path)?;
+                            Ok(Response::from_string("Token updated").with_status_code(200))
+                        }
+                        (Method::Post, "pause") => {
+                            #[derive(Deserialize)]
+                            struct PauseRequest {
+                                reason: String,
+                                until: NaiveDateTime,
+                            }
+
+                            let PauseRequest { reason, until } =
+                                serde_json::from_reader(request.as_reader())?;
+                            let now = master.current_time()?;
+                            let quota = chrono::Duration::hours(i64::from(
+                                master.user.pause.weekly_quota_hours,
+                            ));
+
+                            if master.pauses.current(now).is_some() {
+                                Ok(Response::from_string("Already paused").with_status_code(409))
+                            } else if until <= now {
+                                Ok(Response::from_string("Pause should end in the future")
+                                    .with_status_code(400))
+                            } else if until - now > master.pauses.remaining_quota(now, quota) {
+                                Ok(Response::from_string("Weekly pause quota exceeded")
+                                    .with_status_code(403))
+                            } else {
+                                let pause = Pause {
+                                    reason,
+                                    since: now,
+                                    until,
+                                };
+                                master.pause(pause.clone())?;
+                                Ok(Response::from_string(serde_json::to_string(&pause)?)
+                                    .with_status_code(200))
+                            }
+                        }
+                        (Method::Delete, "pause") => match master.resume()? {
+                            Some(pause) => {
+                                Ok(Response::from_string(serde_json::to_string(&pause)?)
+                                    .with_status_code(200))
+                            }
+                            None => Ok(Response::from_string("Not paused").with_status_code(404)),
+                        },
+                        (Method::Get, "summary") => {
+                            let summary = master.current_summary()?;
+                            Ok(Response::from_string(serde_json::to_string(&summary)?)
+                                .with_status_code(200))
+                        }
+                        _ => Ok(Response::from_string("Not found").with_status_code(404)),
+                    }
+                }
+                _ => Ok(Response::from_string("Not found").with_status_code(404)),
             }
         };
 
         let serving_result = match serve(&mut request) {
             Ok(response) => request.respond(response),
+            // Only the user without the token is affected
+            Err(ref err) if err.downcast_ref::<TokenRequired>().is_some() => {
+                warn!("request handling errored: {}", err);
+                request.respond(
+                    Response::from_string("FitBit token required, POST update_token")
+                        .with_status_code(401),
+                )
+            }
             Err(err) => {
                 error!("request handling errored: {}", err);
                 request.respond(
@@ -91,30 +281,206 @@ fn main() -> Result<(), Error> {
     Ok(())
 }
 
+/// Swap the reloaded config in: users are reconfigured keeping their data, added and removed.
+/// Nothing is changed if any of the new users can't be set up
+fn apply_config(
+    config: &mut Config,
+    mut reloaded: Config,
+    masters: &mut HashMap<String, Headmaster>,
+) -> Result<(), Error> {
+    // Server address and data directory are taken at the startup only
+    if reloaded.network.addr != config.network.addr {
+        warn!("network.addr change requires a restart");
+        reloaded.network = config.network.clone();
+    }
+    if reloaded.storage.data_dir != config.storage.data_dir {
+        warn!("storage.data_dir change requires a restart");
+        reloaded.storage = config.storage.clone();
+    }
+
+    let mut added = HashMap::new();
+    for user in reloaded
+        .users
+        .iter()
+        .filter(|u| !masters.contains_key(&u.id))
+    {
+        let auth = user.auth.clone().unwrap_or_else(|| reloaded.auth.clone());
+        let data_dir = reloaded.storage.user_dir(&user.id);
+        let master = Headmaster::new(auth, user.clone(), data_dir)?;
+        added.insert(user.id.clone(), master);
+    }
+
+    masters.retain(|id, _| reloaded.users.iter().any(|u| u.id == *id));
+    for user in &reloaded.users {
+        if let Some(master) = masters.get_mut(&user.id) {
+            let auth = user.auth.clone().unwrap_or_else(|| reloaded.auth.clone());
+            master.reconfigure(auth, user.clone());
+        }
+    }
+    masters.extend(added);
+
+    *config = reloaded;
+    Ok(())
+}
+
+fn is_authorized(request: &Request, api_key: &str) -> bool {
+    let expected = format!("Bearer {}", api_key);
+    request
+        .headers()
+        .iter()
+        .any(|h| h.field.equiv("Authorization") && h.value.as_str() == expected)
+}
+
 struct Headmaster {
-    options: Options,
-    config: Config,
-    grabber: Option<FitbitActivityGrabber>,
+    auth: Auth,
+    user: User,
+    token_path: PathBuf,
+    grabber: Option<Box<dyn ActivityGrabber>>,
+    timezone: Option<Tz>,
     cache: SummaryCache,
+    history: History,
+    calendars: Vec<Calendar>,
+    pauses: Pauses,
 }
 
-#[derive(Debug, Default, Copy, Clone)]
+#[derive(Debug, Copy, Clone)]
 struct Hour {
+    date: NaiveDate,
     hour: u32,
     complete: bool,
     active_minutes: u32,
+    /// active minutes weighted by the intensity level
+    weighted_active_minutes: u32,
+    detailed: Option<DetailedActivityStats>,
+    /// steps made, if the goal is set in steps
+    steps: Option<u32>,
+    /// activity paying the debt off in the debt unit, normalized by the threshold
     accounted_active_minutes: u32,
     tracking_disabled: bool,
+    tracking_disabled_reason: Option<TrackingDisabledReason>,
+    /// own requirement of the hour from the limits' profile
+    required_minutes: u32,
+    /// requirement of the hour is moved into the next free one
+    requirement_shifted: bool,
+    /// requirement moved from the previous busy hours
+    shifted_requirement: u32,
+    leading_sedentary_minutes: u32,
+    trailing_sedentary_minutes: u32,
+    longest_sedentary_minutes: u32,
+    /// requirement added for a too long sedentary stretch
+    sedentary_penalty: u32,
+    /// requirement prepaid by the overtime credit
+    prepaid_minutes: u32,
+    /// debt forgiven by the decay after the hour
+    forgiven_debt: u32,
     debt: u32,
+    /// debt was capped by the `debt_limit`
+    debt_truncated: bool,
+}
+
+impl Hour {
+    fn new(date: NaiveDate, hour: u32) -> Self {
+        Hour {
+            date,
+            hour,
+            complete: false,
+            active_minutes: 0,
+            weighted_active_minutes: 0,
+            detailed: None,
+            steps: None,
+            accounted_active_minutes: 0,
+            tracking_disabled: false,
+            tracking_disabled_reason: None,
+            required_minutes: 0,
+            requirement_shifted: false,
+            shifted_requirement: 0,
+            leading_sedentary_minutes: 0,
+            trailing_sedentary_minutes: 0,
+            longest_sedentary_minutes: 0,
+            sedentary_penalty: 0,
+            prepaid_minutes: 0,
+            forgiven_debt: 0,
+            debt: 0,
+            debt_truncated: false,
+        }
+    }
+
+    fn start(&self) -> NaiveDateTime {
+        self.date.and_hms(self.hour, 0, 0)
+    }
+
+    /// Full requirement of the hour: its own, the shifted one and the sedentary penalty
+    fn requirement(&self) -> u32 {
+        self.required_minutes + self.shifted_requirement + self.sedentary_penalty
+    }
+
+    /// Activity paying the debt off, in the debt unit
+    fn activity(&self) -> u32 {
+        self.steps.unwrap_or(self.weighted_active_minutes)
+    }
+}
+
+/// Result of the debt calculation over the day window
+struct Calculation {
+    /// last hour with data
+    hour: HourSummary,
+    hours: Vec<Hour>,
+    day_log: Vec<HourSummary>,
+    bank_balance: Option<u32>,
+    plan: Option<Plan>,
+}
+
+/// Time range excluded from the debt calculation
+#[derive(Debug, Copy, Clone)]
+struct ExcludedInterval {
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    reason: TrackingDisabledReason,
+    shift_requirement: bool,
+}
+
+/// Logical day: starts on the `date`, but may end on the next one if the day window crosses midnight.
+/// All the times are local to the user's timezone.
+#[derive(Debug, Copy, Clone)]
+struct DayWindow {
+    date: NaiveDate,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    tz: Tz,
+}
+
+impl DayWindow {
+    /// Add hours to the local time, accounting for the DST transitions
+    fn add_hours(&self, time: NaiveDateTime, hours: i64) -> NaiveDateTime {
+        let duration = chrono::Duration::hours(hours);
+        match self.tz.from_local_datetime(&time).earliest() {
+            Some(time) => (time + duration).naive_local(),
+            // Time is skipped by a DST transition
+            None => time + duration,
+        }
+    }
 }
 
 impl From<Hour> for HourSummary {
     fn from(hour: Hour) -> HourSummary {
         HourSummary {
+            date: hour.date,
             hour: hour.hour,
             debt: hour.debt,
             active_minutes: hour.active_minutes,
+            weighted_active_minutes: hour.weighted_active_minutes,
+            detailed: hour.detailed,
+            steps: hour.steps,
+            unit: if hour.steps.is_some() {
+                Unit::Steps
+            } else {
+                Unit::Minutes
+            },
             tracking_disabled: hour.tracking_disabled,
+            tracking_disabled_reason: hour.tracking_disabled_reason,
+            sedentary_limit_exceeded: hour.sedentary_penalty > 0,
+            required_minutes: hour.requirement(),
+            debt_truncated: hour.debt_truncated,
             complete: hour.complete,
         }
     }
@@ -122,24 +488,28 @@ impl From<Hour> for HourSummary {
 
 struct SummaryCache {
     summary: Option<Summary>,
-    time: DateTime<Local>,
+    time: DateTime<Utc>,
 }
 
 impl SummaryCache {
     pub fn empty() -> Self {
         SummaryCache {
             summary: None,
-            time: Local::now() - chrono::Duration::hours(1),
+            time: Utc::now() - chrono::Duration::hours(1),
         }
     }
 
     pub fn set(&mut self, summary: Summary) {
         self.summary = Some(summary);
-        self.time = Local::now();
+        self.time = Utc::now();
+    }
+
+    pub fn invalidate(&mut self) {
+        self.summary = None;
     }
 
     pub fn get(&self) -> Option<Summary> {
-        if Local::now().signed_duration_since(self.time) < chrono::Duration::minutes(1) {
+        if Utc::now().signed_duration_since(self.time) < chrono::Duration::minutes(1) {
             self.summary.clone()
         } else {
             None
@@ -151,59 +521,143 @@ static NOT_LOGGED_IN_PANIC_MSG: &str =
     "FitbitGrabber not logged into FirBit API. Login should be performed before any request.";
 
 impl Headmaster {
-    pub fn new(config: Config, options: Options) -> Self {
-        Headmaster {
-            config,
-            options,
+    pub fn new(auth: Auth, user: User, data_dir: PathBuf) -> Result<Self, Error> {
+        std::fs::create_dir_all(&data_dir)?;
+        Ok(Headmaster {
+            auth,
+            token_path: data_dir.join("fitbit_token"),
             grabber: None,
+            timezone: user.timezone,
             cache: SummaryCache::empty(),
+            history: History::load(&data_dir)?,
+            pauses: Pauses::load(data_dir.join("pauses.json"))?,
+            calendars: user.calendars.iter().cloned().map(Calendar::new).collect(),
+            user,
+        })
+    }
+
+    /// Apply the reloaded config of the user, the login, history and pauses are kept
+    pub fn reconfigure(&mut self, auth: Auth, user: User) {
+        info!("reconfiguring user {}", user.id);
+        // Timezone from the FitBit profile stays valid unless the configured one changed
+        if user.timezone != self.user.timezone {
+            self.timezone = user.timezone;
         }
+        self.calendars = user.calendars.iter().cloned().map(Calendar::new).collect();
+        self.auth = auth;
+        self.user = user;
+        // Summary is calculated with the previous limits
+        self.cache.invalidate();
     }
 
+    /// Reopen the FitBit session with the stored token. Interactive auth would block the other users' requests,
+    /// so the token has to be supplied with `update_token` if it's missing or expired
     fn login(&mut self) -> Result<(), Error> {
-        let auth_data = load_auth_data(&self.config, &self.options.token_path)?;
-        let grabber = FitbitActivityGrabber::new(&auth_data)?;
+        let auth_data = load_auth_data(&self.auth, &self.token_path)?;
+        let mut grabber = FitbitActivityGrabber::reopen(&auth_data)?;
+        if let Some(thresholds) = self.user.mets_thresholds {
+            grabber.set_mets_thresholds(thresholds);
+        }
         let token = grabber.get_token();
-        token.save(&self.options.token_path)?;
-        self.grabber = Some(grabber);
+        token.save(&self.token_path)?;
+        self.grabber = Some(Box::new(grabber));
         Ok(())
     }
 
-    pub fn current_hour_and_day_log(&mut self) -> Result<(HourSummary, Vec<HourSummary>), Error> {
-        info!("logging into FitBit API");
-        self.login()?;
-        info!("logged in succesfully");
-        let hours = self.get_active_minutes_hourly()?;
+    /// User's timezone: the configured one or the one from the FitBit profile
+    fn timezone(&mut self) -> Result<Tz, Error> {
+        if let Some(tz) = self.timezone {
+            return Ok(tz);
+        }
+
+        if self.grabber.is_none() {
+            self.login()?;
+        }
+
+        let name = self
+            .grabber
+            .as_ref()
+            .expect(NOT_LOGGED_IN_PANIC_MSG)
+            .fetch_timezone()?;
+        let tz = name
+            .parse::<Tz>()
+            .map_err(|e| format_err!("invalid timezone in the FitBit profile: {}", e))?;
+        info!("using timezone {} for user {}", tz.name(), self.user.id);
+
+        self.timezone = Some(tz);
+        Ok(tz)
+    }
+
+    /// Current time local to the user's timezone
+    pub fn current_time(&mut self) -> Result<NaiveDateTime, Error> {
+        let tz = self.timezone()?;
+        Ok(Utc::now().with_timezone(&tz).naive_local())
+    }
+
+    pub fn current_hour_and_day_log(
+        &mut self,
+        schedule: &Schedule,
+        window: DayWindow,
+        now: NaiveDateTime,
+    ) -> Result<Calculation, Error> {
+        for calendar in &mut self.calendars {
+            if let Err(e) = calendar.refresh(window.tz) {
+                warn!("failed to read the calendar: {}", e);
+            }
+        }
+        let hours = self.get_active_minutes_hourly(window, now, &schedule.limits)?;
         debug!("ABSOLUTE DEBT: \n{:#?}", hours);
-        let hours = self.exclude_inactive_hours(hours)?;
+        let (hours, day_end) = self.exclude_inactive_hours(hours, schedule, window, now)?;
         debug!("NORMALIZED BY SLEEPING HOURS: \n{:#?}", hours);
-        let hours = self.normalize_by_threshold(hours);
+        let hours = self.penalize_sedentary_stretches(hours, &schedule.limits);
+        debug!("PENALIZED FOR SEDENTARY STRETCHES: \n{:#?}", hours);
+        let hours = self.normalize_by_threshold(hours, &schedule.limits);
         info!("NORMALIZED BY THRESHOLD: \n{:#?}", hours);
-        let hours = self.calculate_debt_hourly(hours);
+        let normalized = hours.clone();
+        let (hours, bank_balance) = debt::calculate(hours, schedule, &self.user, now);
         info!("HOURLY DEBT CALCULATION: \n{:#?}", hours);
-        let debt = self.calculate_debt(&hours);
-        info!("CURRENT DEBT: {}", debt);
+        info!("CURRENT DEBT: {}", hours.last().map_or(0, |h| h.debt));
 
         let last_hour = hours.last().cloned().unwrap_or_else(|| {
             error!("last hour info is not available");
             Hour {
                 complete: true,
                 tracking_disabled: true,
-                ..Default::default()
+                ..Hour::new(now.date(), now.hour())
             }
         });
 
-        let current_hour_summary = HourSummary {
-            hour: last_hour.hour,
-            debt,
-            complete: last_hour.complete,
-            tracking_disabled: last_hour.tracking_disabled,
-            active_minutes: last_hour.active_minutes,
-        };
+        let plan = projection::project(&normalized, schedule, &self.user, day_end, now);
+        debug!("PROJECTION: {:#?}", plan);
 
-        let day_log = hours.into_iter().map(HourSummary::from).collect();
+        Ok(Calculation {
+            hour: HourSummary::from(last_hour),
+            day_log: hours.iter().cloned().map(HourSummary::from).collect(),
+            hours,
+            bank_balance,
+            plan,
+        })
+    }
 
-        Ok((current_hour_summary, day_log))
+    pub fn pause(&mut self, pause: Pause) -> Result<(), Error> {
+        info!(
+            "pausing debt collection for user {}: {:?}",
+            self.user.id, pause
+        );
+        self.pauses.start(pause)?;
+        self.cache.invalidate();
+        Ok(())
+    }
+
+    pub fn resume(&mut self) -> Result<Option<Pause>, Error> {
+        let now = self.current_time()?;
+        let pause = self.pauses.stop(now)?;
+        info!(
+            "resuming debt collection for user {}: {:?}",
+            self.user.id, pause
+        );
+        self.cache.invalidate();
+        Ok(pause)
     }
 
     pub fn current_summary(&mut self) -> Result<Summary, Error> {
@@ -213,164 +667,671 @@ impl Headmaster {
             return Ok(summary);
         }
 
+        info!("logging user {} into FitBit API", self.user.id);
+        self.login()?;
+        info!("logged in succesfully");
+
+        let now = self.current_time()?;
+        let tz = self.timezone()?;
+        let window = self.current_day_window(now, tz);
+
+        // Previous days are over: record their final logs and unpaid debt,
+        // including the ones the user never requested the summary on
+        let previous = window.date.pred();
+        let oldest = previous - chrono::Duration::days(RETENTION_DAYS - 1);
+        let mut date = match self.history.last_settled() {
+            Some(settled) => NaiveDate::max(settled.succ(), oldest),
+            None => previous,
+        };
+        while date <= previous {
+            if let Err(e) = self.settle(self.day_window(date, tz)) {
+                warn!("failed to settle the day {}: {}", date, e);
+                break;
+            }
+            date = date.succ();
+        }
+
+        let (summary, _) = self.summarize(now)?;
+
+        // Keep the day log for the team view
+        self.history.record(window.date, &summary.day_log)?;
+
+        // Put the summary into the cache
+        self.cache.set(summary.clone());
+
+        Ok(summary)
+    }
+
+    /// Summary of the day window `now` belongs to, along with the calculated hours
+    fn summarize(&mut self, now: NaiveDateTime) -> Result<(Summary, Vec<Hour>), Error> {
+        // Day window and limits may differ depending on the weekday
+        let tz = self.timezone()?;
+        let window = self.current_day_window(now, tz);
+        let schedule = self.user.schedule(window.date.weekday());
+
         // Get last stats from Fitbit
-        let (hour, day_log) = self.current_hour_and_day_log()?;
+        let Calculation {
+            hour,
+            hours,
+            day_log,
+            bank_balance,
+            plan,
+        } = self.current_hour_and_day_log(&schedule, window, now)?;
+
+        // Debt that appeared at the last hour boundary is not collected during the grace period
+        let grace_period = chrono::Duration::minutes(self.user.grace_period_minutes);
+        let deadline = hour.date.and_hms(hour.hour, 0, 0) + grace_period;
+        let debt_is_new = day_log.iter().rev().nth(2).map_or(true, |h| h.debt == 0);
+        let deadline = if hour.debt > 0 && debt_is_new && now < deadline {
+            Some(deadline)
+        } else {
+            None
+        };
 
         // Calculate the correct system state:
-        // 1. debt > 0 and user haven't been active >= max hourly accounted time => DebtCollection
-        // 2. debt > 0 and user can't log more time this hour due to the limit => DebtCollectionPaused
-        // 3. no debt => Normal
-        let max_accounted = self.config.limits.max_accounted_active_time;
-        let state = if hour.debt > 0 && hour.active_minutes < max_accounted {
+        // 1. debt > 0 and the grace period is not over yet => DebtWarning
+        // 2. debt > 0 and user haven't been active >= max hourly accounted time => DebtCollection
+        // 3. debt > 0 and user can't log more time this hour due to the limit => DebtCollectionPaused
+        // 4. no debt => Normal
+        // Manual pause overrides all of them
+        let pause = self.pauses.current(now).cloned();
+        let max_accounted = schedule.limits.max_accounted_active_time;
+        let state = if pause.is_some() {
+            State::Paused(hour)
+        } else if deadline.is_some() {
+            State::DebtWarning(hour)
+        } else if hour.debt > 0 && hour.activity() < max_accounted {
             State::DebtCollection(hour)
-        } else if hour.debt > 0 && hour.active_minutes >= max_accounted {
+        } else if hour.debt > 0 && hour.activity() >= max_accounted {
             State::DebtCollectionPaused(hour)
         } else {
             State::Normal(hour)
         };
 
-        let summary = Summary { state, day_log };
+        let severity = self.severity(state, &day_log, now);
 
-        // Put the summary into the cache
-        self.cache.set(summary.clone());
+        let summary = Summary {
+            state,
+            day_log,
+            pause,
+            bank_balance,
+            unit: schedule.limits.unit,
+            deadline,
+            plan,
+            severity,
+        };
 
-        Ok(summary)
+        Ok((summary, hours))
     }
 
-    fn get_active_minutes_hourly(&self) -> Result<Vec<Hour>, Error> {
-        let data = self
-            .grabber
-            .as_ref()
-            .expect(NOT_LOGGED_IN_PANIC_MSG)
-            .fetch_hourly_activity(Self::current_date())?
+    /// Escalation level of the debt collection: by the debt size and by the time it's collected for
+    fn severity(&self, state: State, day_log: &[HourSummary], now: NaiveDateTime) -> u32 {
+        // Collection is paused once the hour's activity is maxed out, there's nothing more to push for
+        let hour = match state {
+            State::DebtCollection(hour) => hour,
+            _ => return 0,
+        };
+
+        // Debt is collected since the grace period after the earliest hour of the current run of hours with debt
+        let grace_period = chrono::Duration::minutes(self.user.grace_period_minutes);
+        let since = day_log
             .iter()
-            .map(|h| Hour {
-                hour: h.hour,
-                complete: h.complete,
-                active_minutes: h.active_minutes,
-                accounted_active_minutes: h.active_minutes,
-                ..Default::default()
-            })
-            .collect::<Vec<_>>();
+            .rev()
+            .skip(1)
+            .take_while(|h| h.complete && h.debt > 0)
+            .last()
+            .map(|h| h.date.and_hms(h.hour, 0, 0) + chrono::Duration::hours(1) + grace_period)
+            .unwrap_or(now);
+        let collection_minutes = i64::max((now - since).num_minutes(), 0);
 
-        Ok(data)
+        let reached = self
+            .user
+            .severity_levels
+            .iter()
+            .rposition(|level| level.is_reached(hour.debt, collection_minutes))
+            .map_or(0, |i| i as u32 + 1);
+        1 + reached
     }
 
-    fn exclude_inactive_hours(&self, mut hours: Vec<Hour>) -> Result<Vec<Hour>, Error> {
-        // Fetch the sleeping intervals from FitBit API
-        let mut sleep_intervals = self
-            .grabber
-            .as_ref()
-            .expect(NOT_LOGGED_IN_PANIC_MSG)
-            .fetch_sleep_intervals(Self::current_date())?;
+    /// Recalculate the finished day window and record the debt left unpaid when it ended
+    fn settle(&mut self, window: DayWindow) -> Result<(), Error> {
+        let schedule = self.user.schedule(window.date.weekday());
+        let Calculation { hour, day_log, .. } =
+            self.current_hour_and_day_log(&schedule, window, window.end)?;
+        self.history.record(window.date, &day_log)?;
 
-        debug!("sleep intervals: {:#?}", sleep_intervals);
+        let settlement = Settlement {
+            date: window.date,
+            unpaid_debt: hour.debt,
+        };
+        info!(
+            "settling the day for user {}: {:?}",
+            self.user.id, settlement
+        );
+        self.history.settle(settlement)
+    }
 
-        // If no data there, fallback to config defined day start time
-        if sleep_intervals.is_empty() {
-            sleep_intervals.push(SleepInterval {
-                start: NaiveTime::from_hms(0, 0, 0),
-                end: self.config.day.day_begins_at,
-            })
+    /// Logical day the `now` belongs to
+    fn current_day_window(&self, now: NaiveDateTime, tz: Tz) -> DayWindow {
+        let today = now.date();
+        let date = if now < self.day_boundary(today) {
+            today.pred()
+        } else {
+            today
+        };
+
+        self.day_window(date, tz)
+    }
+
+    /// Logical day starting on the `date`
+    fn day_window(&self, date: NaiveDate, tz: Tz) -> DayWindow {
+        DayWindow {
+            date,
+            start: self.day_boundary(date),
+            end: self.day_boundary(date.succ()),
+            tz,
         }
+    }
 
-        // Calculate day end
-        let day_end = sleep_intervals.iter().fold(None, |day_end, interval| {
-            let end = if day_end.is_none() {
-                Some(interval.end + chrono::Duration::hours(self.config.day.day_length))
+    /// Time the logical day of the `date` starts: midnight, or the end of the hour
+    /// the previous day window ends at, if it crosses midnight
+    fn day_boundary(&self, date: NaiveDate) -> NaiveDateTime {
+        let previous = self.user.schedule(date.pred().weekday()).day;
+        if previous.is_overnight() {
+            let end = date.and_time(previous.day_ends_at);
+            let hour_start = date.and_hms(end.hour(), 0, 0);
+            if hour_start == end {
+                end
             } else {
-                day_end.map(|time| time + (interval.end - interval.start))
-            };
+                hour_start + chrono::Duration::hours(1)
+            }
+        } else {
+            date.and_hms(0, 0, 0)
+        }
+    }
+
+    fn get_active_minutes_hourly(
+        &self,
+        window: DayWindow,
+        now: NaiveDateTime,
+        limits: &Limits,
+    ) -> Result<Vec<Hour>, Error> {
+        let grabber = self.grabber.as_ref().expect(NOT_LOGGED_IN_PANIC_MSG);
+
+        // Day window may span two dates
+        let mut data = Vec::new();
+        let mut date = window.start.date();
+        while date <= now.date() {
+            let hours = grabber
+                .fetch_hourly_activity(date)?
+                .iter()
+                .map(|h| {
+                    let weighted_active_minutes = h
+                        .detailed
+                        .map(|detailed| limits.weights.weigh(&detailed))
+                        .unwrap_or(h.active_minutes);
+                    Hour {
+                        // Hours of the previous dates are complete
+                        complete: h.complete || date < now.date(),
+                        active_minutes: h.active_minutes,
+                        weighted_active_minutes,
+                        detailed: h.detailed,
+                        accounted_active_minutes: weighted_active_minutes,
+                        required_minutes: limits.requirement(h.hour),
+                        leading_sedentary_minutes: h.leading_sedentary_minutes,
+                        trailing_sedentary_minutes: h.trailing_sedentary_minutes,
+                        longest_sedentary_minutes: h.longest_sedentary_minutes,
+                        ..Hour::new(date, h.hour)
+                    }
+                })
+                .filter(|h| window.start <= h.start() && h.start() < window.end)
+                .collect::<Vec<_>>();
+            data.extend(hours);
+            date = date.succ();
+        }
 
-            // Check that we're not overflowing the 24-h boundary
-            end.map(|e| {
-                if e < interval.end {
-                    NaiveTime::from_hms(23, 59, 59)
-                } else {
-                    e
+        // Steps replace the active minutes for the debt payment
+        if limits.unit == Unit::Steps {
+            let mut date = window.start.date();
+            while date <= now.date() {
+                for steps in grabber.fetch_hourly_steps(date)? {
+                    let hour = data
+                        .iter_mut()
+                        .find(|h| h.date == date && h.hour == steps.hour);
+                    if let Some(hour) = hour {
+                        hour.steps = Some(steps.steps);
+                        hour.accounted_active_minutes = steps.steps;
+                    }
                 }
+                date = date.succ();
+            }
+            // Hours without any step logged
+            data.iter_mut()
+                .filter(|h| h.steps.is_none())
+                .for_each(|h| h.steps = Some(0));
+        }
+
+        Ok(data)
+    }
+
+    /// Disable tracking of the hours outside the day, asleep, busy or paused, along with the time the day ends at
+    fn exclude_inactive_hours(
+        &self,
+        mut hours: Vec<Hour>,
+        schedule: &Schedule,
+        window: DayWindow,
+        now: NaiveDateTime,
+    ) -> Result<(Vec<Hour>, NaiveDateTime), Error> {
+        let Schedule { day, limits, .. } = schedule;
+
+        // Whole day is excluded: zero debt, zero overtime
+        if schedule.tracking_disabled {
+            hours.iter_mut().for_each(|h| {
+                h.accounted_active_minutes = h.required_minutes;
+                h.tracking_disabled = true;
+                h.tracking_disabled_reason = Some(TrackingDisabledReason::DayOff);
+            });
+            return Ok((hours, window.start));
+        }
+
+        // Fetch the sleeping intervals from FitBit API, the day window may span two dates
+        let grabber = self.grabber.as_ref().expect(NOT_LOGGED_IN_PANIC_MSG);
+        let mut sleep_intervals = Vec::new();
+        let mut date = window.start.date();
+        while date <= now.date() {
+            let intervals = grabber.fetch_sleep_intervals(date)?;
+            sleep_intervals.extend(intervals.into_iter().map(|i| (date, i)));
+            date = date.succ();
+        }
+
+        debug!("sleep intervals: {:#?}", sleep_intervals);
+
+        // Main sleep logged for the day (i.e. ended on the day) defines the wake up time
+        let wake_up = sleep_intervals
+            .iter()
+            .filter(|(date, i)| *date == window.date && i.is_main_sleep)
+            .map(|(_, i)| i.end)
+            .max();
+
+        debug!("woke up at: {:?}", wake_up);
+
+        // Configured day window
+        let day_begins_at = window.date.and_time(day.day_begins_at);
+        let day_ends_at = if day.is_overnight() {
+            window.date.succ().and_time(day.day_ends_at)
+        } else {
+            window.date.and_time(day.day_ends_at)
+        };
+
+        // Naps and the next night's sleep exempt their own hours only
+        let mut excluded_intervals = sleep_intervals
+            .iter()
+            .filter(|(date, i)| !(*date == window.date && i.is_main_sleep))
+            .filter(|(_, i)| i.start < window.end && i.end > window.start)
+            .map(|(_, i)| ExcludedInterval {
+                start: NaiveDateTime::max(i.start, window.start),
+                end: NaiveDateTime::min(i.end, window.end),
+                reason: TrackingDisabledReason::Sleep,
+                shift_requirement: false,
             })
+            .collect::<Vec<_>>();
+
+        // Day starts at the wake up, if no main sleep data there, fallback to config defined day start time.
+        // Time before it is not tracked, even if the sleep started after the day window did
+        let wake_up = wake_up.unwrap_or(day_begins_at);
+        let wake_up = NaiveDateTime::min(NaiveDateTime::max(wake_up, window.start), window.end);
+        excluded_intervals.push(ExcludedInterval {
+            start: window.start,
+            end: wake_up,
+            reason: TrackingDisabledReason::OutsideDay,
+            shift_requirement: false,
         });
 
+        // Calculate day end, configured day end is used regardless of sleep data
+        let day_end = window.add_hours(wake_up, day.day_length);
+        let day_end = NaiveDateTime::min(day_end, day_ends_at);
+
         debug!("day ends at: {:?}", day_end);
 
         // Add the day end interval as well,
-        sleep_intervals.push(SleepInterval {
-            start: day_end.unwrap_or(self.config.day.day_ends_at),
-            end: NaiveTime::from_hms(23, 59, 59),
+        excluded_intervals.push(ExcludedInterval {
+            start: day_end,
+            end: window.end,
+            reason: TrackingDisabledReason::OutsideDay,
+            shift_requirement: false,
         });
 
+        // And the days off and busy time from the calendars, within the day only:
+        // the requirement of the hours outside of it is neither waived nor shifted by the events
+        for calendar in &self.calendars {
+            let events = calendar.events(wake_up, day_end);
+            excluded_intervals.extend(events.into_iter().map(|event| ExcludedInterval {
+                start: event.start,
+                end: event.end,
+                reason: event.reason,
+                shift_requirement: calendar.shifts_requirement(),
+            }));
+        }
+
+        // And the manual pauses
+        let pauses = self.pauses.intervals(window.start, window.end);
+        excluded_intervals.extend(pauses.into_iter().map(|(start, end)| ExcludedInterval {
+            start,
+            end,
+            reason: TrackingDisabledReason::Paused,
+            shift_requirement: false,
+        }));
+
+        debug!("excluded intervals: {:#?}", excluded_intervals);
+
         hours.iter_mut().for_each(|h| {
-            for interval in &sleep_intervals {
+            for interval in &excluded_intervals {
                 // Zero debt, zero overtime
-                let activity_during_sleep = self.config.limits.minimum_active_time;
-                if h.hour >= interval.start.hour() && h.hour < interval.end.hour() {
+                let activity_during_sleep = h.required_minutes;
+                let start_hour = interval.start.date().and_hms(interval.start.hour(), 0, 0);
+                let end_hour = interval.end.date().and_hms(interval.end.hour(), 0, 0);
+                if h.start() >= start_hour && h.start() < end_hour {
                     h.accounted_active_minutes = activity_during_sleep;
                     h.tracking_disabled = true;
-                } else if h.hour == interval.end.hour() {
-                    h.accounted_active_minutes =
-                        u32::min(interval.end.minute(), activity_during_sleep);
-                    if h.accounted_active_minutes == activity_during_sleep {
+                    h.tracking_disabled_reason = Some(interval.reason);
+                    h.requirement_shifted = interval.shift_requirement;
+                } else if h.start() == end_hour {
+                    // Actual activity of the hour is kept if there was more than the covered minutes
+                    let covered_since = NaiveDateTime::max(interval.start, end_hour);
+                    let covered = (interval.end - covered_since).num_minutes() as u32;
+                    let covered = match limits.unit {
+                        Unit::Minutes => covered,
+                        Unit::Steps => covered * h.required_minutes / 60,
+                    };
+                    let covered = u32::min(covered, activity_during_sleep);
+                    h.accounted_active_minutes = u32::max(h.accounted_active_minutes, covered);
+                    if covered == activity_during_sleep {
                         h.tracking_disabled = true;
+                        h.tracking_disabled_reason = Some(interval.reason);
+                        h.requirement_shifted = interval.shift_requirement;
                     }
                 }
             }
         });
 
-        Ok(hours)
-    }
-
-    fn normalize_by_threshold(&self, mut hours: Vec<Hour>) -> Vec<Hour> {
-        hours.iter_mut().for_each(|h| {
-            let limits = &self.config.limits;
-            h.accounted_active_minutes =
-                u32::min(h.accounted_active_minutes, limits.max_accounted_active_time);
-            h.debt = u32::min(h.debt, limits.debt_limit);
-        });
+        // Move the requirement of the complete busy hours into the next free hour
+        let mut shifted = 0;
+        for h in hours.iter_mut() {
+            if !h.tracking_disabled {
+                h.shifted_requirement = shifted;
+                shifted = 0;
+            } else if h.requirement_shifted && h.complete {
+                shifted += h.required_minutes;
+            }
+        }
 
-        hours
+        Ok((hours, day_end))
     }
 
-    fn calculate_debt_hourly(&self, mut hours: Vec<Hour>) -> Vec<Hour> {
-        let limits = &self.config.limits;
+    /// Add a requirement to the hour a sedentary stretch exceeds the limit in,
+    /// the stretches are joined across the hour boundaries
+    fn penalize_sedentary_stretches(&self, mut hours: Vec<Hour>, limits: &Limits) -> Vec<Hour> {
+        let max_sedentary = match limits.max_sedentary_time {
+            Some(max_sedentary) => max_sedentary,
+            None => return hours,
+        };
 
-        // Calculate first hour activity debt
-        hours[0].debt = limits
-            .minimum_active_time
-            .checked_sub(hours[0].accounted_active_minutes)
-            .unwrap_or(0);
+        // Stretch continuing from the previous hours, and whether it was already penalized
+        let mut stretch = 0;
+        let mut penalized = false;
+        for hour in &mut hours {
+            // Excluded time breaks the stretch
+            if hour.tracking_disabled {
+                stretch = 0;
+                penalized = false;
+                continue;
+            }
 
-        for i in 1..hours.len() {
-            // Next hour debt is previous hour debt + current hour default debt
-            let current_hour_minimum = if hours[i].complete {
-                limits.minimum_active_time
+            let exceeded = if hour.active_minutes == 0 {
+                // Sedentary the whole hour: the stretch goes on
+                stretch += hour.leading_sedentary_minutes;
+                let exceeded = !penalized && stretch > max_sedentary;
+                penalized |= exceeded;
+                exceeded
             } else {
-                0
+                let exceeded = (!penalized
+                    && stretch + hour.leading_sedentary_minutes > max_sedentary)
+                    || hour.longest_sedentary_minutes > max_sedentary;
+                stretch = hour.trailing_sedentary_minutes;
+                penalized = stretch > max_sedentary;
+                exceeded
             };
 
-            hours[i].debt = (current_hour_minimum + hours[i - 1].debt)
-                .checked_sub(hours[i].accounted_active_minutes)
-                .unwrap_or(0)
+            if exceeded {
+                hour.sedentary_penalty = limits.minimum_active_time;
+            }
         }
 
         hours
     }
 
-    fn calculate_debt(&self, hours: &[Hour]) -> u32 {
-        hours.last().map(|h| h.debt).unwrap_or(0)
-    }
+    fn normalize_by_threshold(&self, mut hours: Vec<Hour>, limits: &Limits) -> Vec<Hour> {
+        hours.iter_mut().for_each(|h| {
+            h.accounted_active_minutes =
+                u32::min(h.accounted_active_minutes, limits.max_accounted_active_time);
+        });
 
-    fn current_date() -> NaiveDate {
-        Local::today().naive_local()
+        hours
     }
 }
 
-fn load_auth_data(config: &Config, token_path: &Path) -> Result<FitbitAuthData, Error> {
-    let id = config.auth.client_id.clone();
-    let secret = config.auth.client_secret.clone();
+fn load_auth_data(auth: &Auth, token_path: &Path) -> Result<FitbitAuthData, Error> {
+    let id = auth.client_id.clone();
+    let secret = auth.client_secret.clone();
     let token = FitbitToken::load(token_path)
         .map_err(|e| "failed to read FitBit token: {}, e")
         .ok();
 
     Ok(FitbitAuthData { id, secret, token })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{CalendarKind, CalendarSource};
+    use crate::simulate::{Fixture, FixtureSleep};
+    use crate::testing::{at, fixture, master, temp_dir, user};
+
+    /// Hours of the day summarized at `now`
+    fn summarize(user: User, fixture: Fixture) -> Vec<Hour> {
+        let now = fixture.now;
+        let (_, hours) = master(user, fixture).summarize(now).unwrap();
+        hours
+    }
+
+    fn hour(hours: &[Hour], hour: u32) -> Hour {
+        *hours.iter().find(|h| h.hour == hour).unwrap()
+    }
+
+    #[test]
+    fn busy_events_before_the_day_are_not_shifted() {
+        let path = temp_dir().join("meetings.ics");
+        std::fs::write(
+            &path,
+            "BEGIN:VCALENDAR\nBEGIN:VEVENT\nDTSTART:20190107T080000\nDTEND:20190107T090000\n\
+             END:VEVENT\nEND:VCALENDAR\n",
+        )
+        .unwrap();
+        let mut user = user();
+        user.calendars = vec![CalendarSource {
+            path,
+            kind: CalendarKind::Busy,
+            shift_requirement: true,
+        }];
+
+        let hours = summarize(user, fixture(at(11, 30), at(8, 0), &[0, 0, 5, 0]));
+        assert_eq!(
+            hour(&hours, 8).tracking_disabled_reason,
+            Some(TrackingDisabledReason::OutsideDay)
+        );
+        assert_eq!(hour(&hours, 10).requirement(), 5);
+        assert_eq!(hour(&hours, 10).debt, 0);
+    }
+
+    /// Fixture of the hours from 10:00 with the active, the leading and the trailing sedentary minutes
+    fn sitting(now: NaiveDateTime, stats: &[(u32, u32, u32)]) -> Fixture {
+        let active_minutes = stats.iter().map(|s| s.0).collect::<Vec<_>>();
+        let mut fixture = fixture(now, at(10, 0), &active_minutes);
+        for (hour, &(_, leading, trailing)) in fixture.hours.iter_mut().zip(stats) {
+            hour.leading_sedentary_minutes = Some(leading);
+            hour.trailing_sedentary_minutes = Some(trailing);
+        }
+        fixture
+    }
+
+    fn sitting_user() -> User {
+        let mut user = user();
+        user.limits.max_sedentary_time = Some(50);
+        user
+    }
+
+    fn penalties(hours: &[Hour]) -> Vec<u32> {
+        hours.iter().map(|h| h.sedentary_penalty).collect()
+    }
+
+    #[test]
+    fn sitting_until_the_hour_end_is_penalized() {
+        // 10:05 to 11:00 sitting followed by a walk meets the hourly requirement, but not the sedentary limit
+        let hours = summarize(
+            sitting_user(),
+            sitting(at(12, 0), &[(5, 0, 55), (30, 0, 30)]),
+        );
+        assert_eq!(penalties(&hours), vec![5, 0]);
+    }
+
+    #[test]
+    fn stretch_across_two_boundaries_is_penalized_once() {
+        // 10:40 to 12:15
+        let hours = summarize(
+            sitting_user(),
+            sitting(at(13, 0), &[(20, 20, 20), (0, 60, 60), (5, 15, 40)]),
+        );
+        assert_eq!(penalties(&hours), vec![0, 5, 0]);
+    }
+
+    #[test]
+    fn stretch_is_broken_by_the_excluded_time() {
+        let stats = [(20, 0, 40), (0, 60, 60), (5, 40, 15)];
+        let hours = summarize(sitting_user(), sitting(at(13, 0), &stats));
+        assert_eq!(penalties(&hours), vec![0, 5, 0]);
+
+        // Nap at 11:00 ends the 10:20 stretch, the one since 12:00 is too short
+        let mut fixture = sitting(at(13, 0), &stats);
+        fixture.sleep.push(FixtureSleep {
+            start: at(11, 0),
+            end: at(12, 0),
+            is_main_sleep: false,
+        });
+        let hours = summarize(sitting_user(), fixture);
+        assert_eq!(
+            hour(&hours, 11).tracking_disabled_reason,
+            Some(TrackingDisabledReason::Sleep)
+        );
+        assert_eq!(penalties(&hours), vec![0, 0, 0]);
+    }
+
+    /// User with the day window from 14:00 to the `ends_at` of the next date
+    fn night_owl(ends_at: &str) -> User {
+        let mut user = user();
+        user.day.day_begins_at = "14:00:00".parse().unwrap();
+        user.day.day_ends_at = ends_at.parse().unwrap();
+        user.day.day_length = 12;
+        user
+    }
+
+    #[test]
+    fn overnight_day_continues_after_midnight() {
+        let next_day = |h, m| at(h, m) + chrono::Duration::days(1);
+        let now = next_day(1, 30);
+        let active_minutes = vec![5; 13];
+        let mut master = master(
+            night_owl("02:00:00"),
+            fixture(now, at(13, 0), &active_minutes),
+        );
+
+        let window = master.current_day_window(now, chrono_tz::UTC);
+        assert_eq!(window.date, at(0, 0).date());
+        assert_eq!((window.start, window.end), (at(2, 0), next_day(2, 0)));
+
+        let (_, hours) = master.summarize(now).unwrap();
+        let last = *hours.last().unwrap();
+        assert_eq!((last.date, last.hour), (now.date(), 1));
+        assert!(!last.tracking_disabled);
+        assert_eq!(
+            hour(&hours, 13).tracking_disabled_reason,
+            Some(TrackingDisabledReason::OutsideDay)
+        );
+        assert!(!hour(&hours, 14).tracking_disabled);
+        assert_eq!(hour(&hours, 23).debt, 0);
+    }
+
+    #[test]
+    fn day_boundary_is_rounded_up_to_the_hour_end() {
+        let next_day = |h, m| at(h, m) + chrono::Duration::days(1);
+        let master = master(night_owl("01:30:00"), fixture(at(0, 0), at(0, 0), &[]));
+
+        assert_eq!(master.day_boundary(next_day(0, 0).date()), next_day(2, 0));
+        let window = master.current_day_window(next_day(1, 45), chrono_tz::UTC);
+        assert_eq!(window.date, at(0, 0).date());
+        let window = master.current_day_window(next_day(2, 0), chrono_tz::UTC);
+        assert_eq!(window.date, next_day(0, 0).date());
+    }
+
+    #[test]
+    fn nap_exempts_its_own_hour_only() {
+        let mut fixture = fixture(at(15, 30), at(8, 0), &[0, 0, 5, 5, 5, 0, 5, 5]);
+        fixture.sleep = vec![
+            FixtureSleep {
+                start: at(0, 0) - chrono::Duration::hours(1),
+                end: at(9, 40),
+                is_main_sleep: true,
+            },
+            FixtureSleep {
+                start: at(13, 0),
+                end: at(14, 0),
+                is_main_sleep: false,
+            },
+        ];
+        let hours = summarize(user(), fixture);
+
+        // Day starts at the wake up rather than at the configured 10:00
+        assert_eq!(
+            hour(&hours, 9).tracking_disabled_reason,
+            Some(TrackingDisabledReason::OutsideDay)
+        );
+        assert!(!hour(&hours, 10).tracking_disabled);
+        assert_eq!(
+            hour(&hours, 13).tracking_disabled_reason,
+            Some(TrackingDisabledReason::Sleep)
+        );
+        assert_eq!(hour(&hours, 13).debt, 0);
+        assert!(!hour(&hours, 14).tracking_disabled);
+    }
+
+    #[test]
+    fn main_sleep_ending_on_the_date_defines_the_wake_up() {
+        // Went to bed after midnight and slept in
+        let mut fixture = fixture(at(12, 30), at(0, 0), &[0; 13]);
+        fixture.sleep.push(FixtureSleep {
+            start: at(1, 30),
+            end: at(11, 0),
+            is_main_sleep: true,
+        });
+        let hours = summarize(user(), fixture);
+
+        // Time before the sleep is outside of the day as well
+        for h in 0..11 {
+            assert_eq!(
+                hour(&hours, h).tracking_disabled_reason,
+                Some(TrackingDisabledReason::OutsideDay)
+            );
+        }
+        assert!(!hour(&hours, 11).tracking_disabled);
+        assert_eq!(hour(&hours, 11).debt, 5);
+    }
+}
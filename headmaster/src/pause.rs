@@ -0,0 +1,89 @@
+use crate::history::save_json;
+use chrono::{Datelike, Duration, NaiveDateTime};
+use failure::Error;
+use headmaster::Pause;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// Log of the user's manual pauses, persisted so that the weekly quota survives restarts
+pub struct Pauses {
+    path: PathBuf,
+    log: Vec<Pause>,
+}
+
+impl Pauses {
+    /// Load the pauses log from `path`, a new log is started if there's no file yet
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref().to_owned();
+        let log = if path.exists() {
+            serde_json::from_reader(File::open(&path)?)?
+        } else {
+            vec![]
+        };
+
+        Ok(Pauses { path, log })
+    }
+
+    pub fn current(&self, now: NaiveDateTime) -> Option<&Pause> {
+        self.log
+            .iter()
+            .find(|pause| pause.since <= now && now < pause.until)
+    }
+
+    /// Pause time left in the ISO week of `now`
+    pub fn remaining_quota(&self, now: NaiveDateTime, weekly_quota: Duration) -> Duration {
+        let week = now.date().iso_week();
+        let used = self
+            .log
+            .iter()
+            .filter(|pause| pause.since.date().iso_week() == week)
+            .fold(Duration::zero(), |used, pause| {
+                used + (pause.until - pause.since)
+            });
+
+        weekly_quota - used
+    }
+
+    pub fn start(&mut self, pause: Pause) -> Result<(), Error> {
+        self.log.push(pause);
+        self.save()
+    }
+
+    /// End the current pause early, the time left is returned to the quota
+    pub fn stop(&mut self, now: NaiveDateTime) -> Result<Option<Pause>, Error> {
+        let current = self
+            .log
+            .iter_mut()
+            .find(|pause| pause.since <= now && now < pause.until);
+
+        let stopped = current.map(|pause| {
+            pause.until = now;
+            pause.clone()
+        });
+
+        self.save()?;
+        Ok(stopped)
+    }
+
+    /// Parts of the pauses falling into the `from..to` range
+    pub fn intervals(
+        &self,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+        self.log
+            .iter()
+            .filter(|pause| pause.since < to && pause.until > from)
+            .map(|pause| {
+                (
+                    NaiveDateTime::max(pause.since, from),
+                    NaiveDateTime::min(pause.until, to),
+                )
+            })
+            .collect()
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        save_json(&self.path, &self.log)
+    }
+}
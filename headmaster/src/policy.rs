@@ -0,0 +1,291 @@
+use crate::config::{Day, DebtPolicyKind, Limits};
+use crate::Hour;
+
+/// Rule turning the hourly activity into the debt
+pub trait DebtPolicy {
+    /// Fill in the debt of the `hours`, the last hour's debt is the current one
+    fn calculate(&self, hours: &mut [Hour], limits: &Limits);
+}
+
+impl DebtPolicyKind {
+    pub fn policy(&self, day: &Day) -> Box<dyn DebtPolicy> {
+        match *self {
+            DebtPolicyKind::HourlyChain => Box::new(HourlyChain),
+            DebtPolicyKind::RollingWindow { max_sedentary } => {
+                Box::new(RollingWindow { max_sedentary })
+            }
+            DebtPolicyKind::DailyQuota { quota } => Box::new(DailyQuota {
+                quota,
+                day_length: day.day_length as u32,
+            }),
+        }
+    }
+}
+
+/// Set the hour's debt capped by the `debt_limit`, returns the truncated excess
+fn set_debt(hour: &mut Hour, debt: u32, limits: &Limits) -> u32 {
+    hour.debt = u32::min(debt, limits.debt_limit);
+    hour.debt_truncated = debt > limits.debt_limit;
+    debt - hour.debt
+}
+
+/// Every complete hour adds its requirement to the debt, active minutes pay it back
+pub struct HourlyChain;
+
+impl DebtPolicy for HourlyChain {
+    fn calculate(&self, hours: &mut [Hour], limits: &Limits) {
+        // Day window may have just started with no data synchronized yet
+        if hours.is_empty() {
+            return;
+        }
+
+        // Calculate first hour activity debt
+        let debt = hours[0]
+            .requirement()
+            .checked_sub(hours[0].accounted_active_minutes)
+            .unwrap_or(0);
+        set_debt(&mut hours[0], debt, limits);
+
+        for i in 1..hours.len() {
+            // Next hour debt is previous hour debt + current hour default debt
+            let current_hour_minimum = if hours[i].complete {
+                hours[i].required_minutes + hours[i].shifted_requirement
+            } else {
+                0
+            };
+
+            // Capped debt is carried over, so that the excess is never collected
+            let debt = (current_hour_minimum + hours[i].sedentary_penalty + hours[i - 1].debt)
+                .checked_sub(hours[i].accounted_active_minutes)
+                .unwrap_or(0);
+            set_debt(&mut hours[i], debt, limits);
+        }
+    }
+}
+
+/// Sitting for no more than `max_sedentary` minutes in a row, regardless of the hour boundaries:
+/// every `max_sedentary` minutes of a sedentary stretch add the hour's requirement to the debt,
+/// the activity after the stretch pays it off
+pub struct RollingWindow {
+    max_sedentary: u32,
+}
+
+impl RollingWindow {
+    /// Debt-adding windows in the `stretch` minutes of sitting
+    fn windows(&self, stretch: u32) -> u32 {
+        stretch.checked_sub(1).unwrap_or(0) / self.max_sedentary
+    }
+}
+
+impl DebtPolicy for RollingWindow {
+    fn calculate(&self, hours: &mut [Hour], limits: &Limits) {
+        // Stretch continuing from the previous hours
+        let mut stretch = 0;
+        let mut debt = 0;
+        for hour in hours.iter_mut() {
+            // Excluded time breaks the stretch, the debt is kept until the next tracked hour
+            if hour.tracking_disabled {
+                stretch = 0;
+                hour.debt = debt;
+                continue;
+            }
+
+            // Windows the hour's activity follows, and the ones still going on at its end
+            let (paid, unpaid) = if hour.active_minutes == 0 {
+                let windows =
+                    self.windows(stretch + hour.leading_sedentary_minutes) - self.windows(stretch);
+                stretch += hour.leading_sedentary_minutes;
+                (0, windows)
+            } else {
+                let leading =
+                    self.windows(stretch + hour.leading_sedentary_minutes) - self.windows(stretch);
+                // Longest stretch is the one between the activities unless it's the leading or the trailing one
+                let inner = if hour.longest_sedentary_minutes
+                    > u32::max(
+                        hour.leading_sedentary_minutes,
+                        hour.trailing_sedentary_minutes,
+                    ) {
+                    self.windows(hour.longest_sedentary_minutes)
+                } else {
+                    0
+                };
+                stretch = hour.trailing_sedentary_minutes;
+                (leading + inner, self.windows(stretch))
+            };
+
+            let requirement = hour.required_minutes;
+            debt = (debt + paid * requirement + hour.sedentary_penalty)
+                .checked_sub(hour.accounted_active_minutes)
+                .unwrap_or(0)
+                + unpaid * requirement;
+            // Capped debt is carried over, so that the excess is never collected
+            set_debt(hour, debt, limits);
+            debt = hour.debt;
+        }
+    }
+}
+
+/// Daily `quota` of active minutes spread evenly over the hours of the waking day:
+/// surplus of the earlier hours covers the later ones
+pub struct DailyQuota {
+    quota: u32,
+    day_length: u32,
+}
+
+impl DebtPolicy for DailyQuota {
+    fn calculate(&self, hours: &mut [Hour], limits: &Limits) {
+        let mut tracked_hours = 0;
+        let mut active = 0;
+        let mut penalties = 0;
+        // Debt truncated by the limit is never collected
+        let mut written_off = 0;
+
+        for hour in hours.iter_mut() {
+            // Excluded hours neither add to the quota nor count as activity,
+            // unless their requirement is shifted to the later hours
+            if hour.complete && (!hour.tracking_disabled || hour.requirement_shifted) {
+                tracked_hours += 1;
+            }
+            if !hour.tracking_disabled {
+                active += hour.accounted_active_minutes;
+            }
+            penalties += hour.sedentary_penalty;
+
+            let quota_share =
+                u32::min(tracked_hours, self.day_length) * self.quota / self.day_length;
+            let debt = (quota_share + penalties)
+                .checked_sub(active + written_off)
+                .unwrap_or(0);
+            written_off += set_debt(hour, debt, limits);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{day, hours, limits};
+
+    fn calculate(policy: DebtPolicyKind, active_minutes: &[u32]) -> Vec<Hour> {
+        let limits = limits(policy);
+        let mut hours = hours(active_minutes);
+        policy.policy(&day()).calculate(&mut hours, &limits);
+        hours
+    }
+
+    fn debts(hours: &[Hour]) -> Vec<u32> {
+        hours.iter().map(|h| h.debt).collect()
+    }
+
+    fn truncated(hours: &[Hour]) -> Vec<bool> {
+        hours.iter().map(|h| h.debt_truncated).collect()
+    }
+
+    #[test]
+    fn hourly_chain_caps_long_sedentary_streak() {
+        let hours = calculate(DebtPolicyKind::HourlyChain, &[0; 8]);
+        assert_eq!(debts(&hours), vec![5, 10, 15, 15, 15, 15, 15, 15]);
+        assert_eq!(
+            truncated(&hours),
+            vec![false, false, false, true, true, true, true, false]
+        );
+    }
+
+    #[test]
+    fn hourly_chain_pays_off_capped_debt() {
+        // Excess over the limit is not carried over
+        let hours = calculate(DebtPolicyKind::HourlyChain, &[0, 0, 0, 0, 0, 0, 15, 5, 0]);
+        assert_eq!(debts(&hours), vec![5, 10, 15, 15, 15, 15, 5, 5, 5]);
+        assert!(!hours[6].debt_truncated);
+    }
+
+    #[test]
+    fn hourly_chain_current_hour_pays_off_debt() {
+        let hours = calculate(DebtPolicyKind::HourlyChain, &[0, 0, 0, 0, 0, 12]);
+        assert_eq!(hours.last().unwrap().debt, 3);
+    }
+
+    const ROLLING_WINDOW: DebtPolicyKind = DebtPolicyKind::RollingWindow { max_sedentary: 50 };
+
+    /// Hours starting at 10:00 with the active, the leading and the trailing sedentary minutes
+    fn sitting(stats: &[(u32, u32, u32)]) -> Vec<Hour> {
+        let active_minutes = stats.iter().map(|s| s.0).collect::<Vec<_>>();
+        hours(&active_minutes)
+            .into_iter()
+            .zip(stats)
+            .map(|(hour, &(_, leading, trailing))| Hour {
+                leading_sedentary_minutes: leading,
+                trailing_sedentary_minutes: trailing,
+                longest_sedentary_minutes: u32::max(leading, trailing),
+                ..hour
+            })
+            .collect()
+    }
+
+    fn calculate_sitting(stats: &[(u32, u32, u32)]) -> Vec<Hour> {
+        let mut hours = sitting(stats);
+        ROLLING_WINDOW
+            .policy(&day())
+            .calculate(&mut hours, &limits(ROLLING_WINDOW));
+        hours
+    }
+
+    #[test]
+    fn rolling_window_allows_short_stretches() {
+        let hours = calculate_sitting(&[(10, 20, 20), (10, 20, 20), (10, 20, 20)]);
+        assert_eq!(debts(&hours), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn rolling_window_stretch_is_not_split_by_the_hour_boundary() {
+        // 10:05 to 11:00 sitting is collected before the walk at 11:00 pays it off
+        let hours = calculate_sitting(&[(5, 0, 55), (5, 0, 0)]);
+        assert_eq!(debts(&hours), vec![5, 0]);
+
+        let hours = calculate_sitting(&[(5, 0, 30), (5, 30, 25)]);
+        assert_eq!(debts(&hours), vec![0, 0]);
+    }
+
+    #[test]
+    fn rolling_window_long_sitting_accumulates() {
+        let hours = calculate_sitting(&[(0, 60, 60); 4]);
+        assert_eq!(debts(&hours), vec![5, 10, 15, 15]);
+        assert!(hours[3].debt_truncated);
+    }
+
+    #[test]
+    fn rolling_window_activity_before_the_stretch_does_not_pay() {
+        let hours = calculate_sitting(&[(10, 0, 55)]);
+        assert_eq!(debts(&hours), vec![5]);
+    }
+
+    #[test]
+    fn rolling_window_excluded_hour_breaks_the_stretch() {
+        let mut hours = sitting(&[(0, 60, 60), (0, 60, 60), (0, 30, 30)]);
+        hours[1].tracking_disabled = true;
+        hours[1].accounted_active_minutes = hours[1].required_minutes;
+        ROLLING_WINDOW
+            .policy(&day())
+            .calculate(&mut hours, &limits(ROLLING_WINDOW));
+        assert_eq!(debts(&hours), vec![5, 5, 5]);
+    }
+
+    #[test]
+    fn daily_quota_caps_long_sedentary_streak() {
+        let hours = calculate(DebtPolicyKind::DailyQuota { quota: 60 }, &[0; 8]);
+        assert_eq!(debts(&hours), vec![6, 12, 15, 15, 15, 15, 15, 15]);
+        assert_eq!(
+            truncated(&hours),
+            vec![false, false, true, true, true, true, true, false]
+        );
+    }
+
+    #[test]
+    fn daily_quota_surplus_covers_later_hours() {
+        let hours = calculate(
+            DebtPolicyKind::DailyQuota { quota: 60 },
+            &[30, 0, 0, 0, 0, 0],
+        );
+        assert_eq!(debts(&hours), vec![0, 0, 0, 0, 0, 0]);
+    }
+}
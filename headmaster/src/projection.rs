@@ -0,0 +1,175 @@
+use crate::config::{Schedule, User};
+use crate::{debt, Hour};
+use chrono::{Duration, NaiveDateTime, Timelike};
+use headmaster::{Plan, Unit};
+
+/// Project the debt forward by simulating the debt calculation over the rest of the hour and of the day,
+/// ending at `day_end`. Takes the normalized `hours`, i.e. before the credit and the debt are applied.
+/// Future hours are assumed to be tracked and as active as the current one so far.
+pub fn project(
+    hours: &[Hour],
+    schedule: &Schedule,
+    user: &User,
+    day_end: NaiveDateTime,
+    now: NaiveDateTime,
+) -> Option<Plan> {
+    let current = *hours.last()?;
+    let hour_end = current.start() + Duration::hours(1);
+    // Nothing left to plan within the hour if it's over, even if FitBit has not synced it yet
+    if current.complete || current.tracking_disabled || now >= hour_end {
+        return None;
+    }
+
+    let limits = &schedule.limits;
+    let max_accounted = limits.max_accounted_active_time;
+
+    // Debts of the simulated hours, along with the credit and the decay
+    let simulate = |hours: Vec<Hour>| {
+        let (hours, _) = debt::calculate(hours, schedule, user, now);
+        hours.iter().map(|h| h.debt).collect::<Vec<_>>()
+    };
+    // Hour with `extra` more activity
+    let add_activity = |hour: &mut Hour, extra: u32| {
+        match &mut hour.steps {
+            Some(steps) => *steps += extra,
+            None => hour.weighted_active_minutes += extra,
+        }
+        hour.accounted_active_minutes =
+            u32::min(hour.accounted_active_minutes + extra, max_accounted);
+    };
+    // Current hour is complete with `extra` more activity
+    let complete_current = |extra: u32| {
+        let mut hours = hours.to_vec();
+        let last = hours.last_mut().expect("hours are not empty");
+        last.complete = true;
+        add_activity(last, extra);
+        hours
+    };
+
+    let debts = simulate(hours.to_vec());
+    let current_debt = debts.last().cloned().unwrap_or(0);
+
+    // The hour should not end with more debt than it started with
+    let carried_debt = debts.iter().rev().nth(1).cloned().unwrap_or(0);
+    let headroom = max_accounted
+        .checked_sub(current.accounted_active_minutes)
+        .unwrap_or(0);
+    let required_this_hour = (0..=headroom).find(|&extra| {
+        simulate(complete_current(extra))
+            .last()
+            .map_or(true, |&debt| debt <= carried_debt)
+    });
+
+    // Active minutes take time, steps are made at any pace
+    let start_moving_by = match limits.unit {
+        Unit::Minutes => required_this_hour
+            .map(|required| hour_end - Duration::minutes(i64::from(required)))
+            .map(|time| NaiveDateTime::max(time, now)),
+        Unit::Steps => None,
+    };
+
+    // Time the debt is cleared at the current hour's pace:
+    // exact during the current hour, rounded up to the hour end for the later ones
+    let elapsed = i64::max((now - current.start()).num_minutes(), 0) as u32;
+    let remaining = 60u32.checked_sub(elapsed).unwrap_or(0);
+    let accounted = current.accounted_active_minutes;
+    let cleared_at = if current_debt == 0 {
+        Some(now)
+    } else if elapsed == 0 || accounted == 0 {
+        None
+    } else if accounted + current_debt <= max_accounted
+        && current_debt * elapsed / accounted < remaining
+    {
+        Some(now + Duration::minutes(i64::from(current_debt * elapsed / accounted)))
+    } else {
+        let hourly_pace = accounted * 60 / elapsed;
+        let mut hours = complete_current(hourly_pace * remaining / 60);
+        let mut end = hour_end;
+        loop {
+            if simulate(hours.clone()).last() == Some(&0) {
+                break Some(end);
+            }
+            if end >= day_end {
+                break None;
+            }
+            let mut hour = Hour {
+                complete: true,
+                steps: current.steps.map(|_| 0),
+                required_minutes: limits.requirement(end.hour()),
+                ..Hour::new(end.date(), end.hour())
+            };
+            add_activity(&mut hour, hourly_pace);
+            hours.push(hour);
+            end += Duration::hours(1);
+        }
+    };
+
+    Some(Plan {
+        required_this_hour,
+        start_moving_by,
+        cleared_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{DebtPolicyKind, Decay};
+    use crate::testing::{at, hours, schedule, user};
+
+    fn planned(
+        user: &User,
+        active_minutes: &[u32],
+        day_end: NaiveDateTime,
+        now: NaiveDateTime,
+    ) -> Option<Plan> {
+        let schedule = schedule(DebtPolicyKind::HourlyChain);
+        project(&hours(active_minutes), &schedule, user, day_end, now)
+    }
+
+    #[test]
+    fn required_this_hour_keeps_the_debt() {
+        let plan = planned(&user(), &[0, 0], at(20, 0), at(11, 20)).unwrap();
+        assert_eq!(plan.required_this_hour, Some(5));
+        assert_eq!(plan.start_moving_by, Some(at(11, 55)));
+    }
+
+    #[test]
+    fn debt_is_cleared_during_the_current_hour() {
+        let plan = planned(&user(), &[0, 4], at(20, 0), at(11, 20)).unwrap();
+        assert_eq!(plan.cleared_at, Some(at(11, 25)));
+    }
+
+    #[test]
+    fn debt_is_cleared_during_the_later_hours() {
+        let plan = planned(&user(), &[0, 0, 0, 5], at(20, 0), at(13, 20)).unwrap();
+        assert_eq!(plan.cleared_at, Some(at(15, 0)));
+    }
+
+    #[test]
+    fn debt_is_not_cleared_after_the_day_ends() {
+        let plan = planned(&user(), &[0, 0, 0, 5], at(14, 0), at(13, 20)).unwrap();
+        assert_eq!(plan.cleared_at, None);
+    }
+
+    #[test]
+    fn no_plan_once_the_hour_is_over() {
+        assert_eq!(planned(&user(), &[0, 0], at(20, 0), at(12, 30)), None);
+    }
+
+    #[test]
+    fn decay_is_projected() {
+        let mut decaying = user();
+        decaying.decay = Some(Decay { percent: 20 });
+
+        // 12:00 forgives 2 of 10, the current hour has to end with no more than 8
+        let plan = planned(&decaying, &[0, 0, 5, 0], at(20, 0), at(13, 20)).unwrap();
+        assert_eq!(plan.required_this_hour, Some(5));
+
+        // Good hours forgive the debt on top of paying it off at 7 minutes an hour
+        let plan = planned(&decaying, &[0, 0, 0, 3], at(20, 0), at(13, 25)).unwrap();
+        assert_eq!(plan.cleared_at, Some(at(19, 0)));
+        let plan = planned(&user(), &[0, 0, 0, 3], at(20, 0), at(13, 25)).unwrap();
+        assert_eq!(plan.cleared_at, None);
+    }
+}
@@ -0,0 +1,59 @@
+use crate::config::Config;
+use log::{error, info};
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// Config files, the config is reloaded once any of them is modified, added or removed
+pub struct ConfigWatcher {
+    path: PathBuf,
+    modified: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: PathBuf) -> Self {
+        let mut watcher = ConfigWatcher {
+            path,
+            modified: vec![],
+        };
+        watcher.modified = watcher.snapshot();
+        watcher
+    }
+
+    /// Modification times of the config sources, missing files have none
+    fn snapshot(&self) -> Vec<(PathBuf, Option<SystemTime>)> {
+        Config::sources(&self.path)
+            .unwrap_or_else(|_| vec![self.path.clone()])
+            .into_iter()
+            .map(|path| {
+                let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+                (path, modified)
+            })
+            .collect()
+    }
+
+    /// Reloaded config if the sources changed since the last check.
+    /// Invalid config is rejected until the sources change again
+    pub fn poll(&mut self) -> Option<Config> {
+        let snapshot = self.snapshot();
+        if snapshot == self.modified {
+            return None;
+        }
+        self.modified = snapshot;
+
+        match Config::load(&self.path) {
+            Ok(config) => {
+                info!("config {} reloaded", self.path.display());
+                Some(config)
+            }
+            Err(e) => {
+                error!(
+                    "config {} reload rejected, keeping the previous one: {}",
+                    self.path.display(),
+                    e
+                );
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,313 @@
+use crate::config::Config;
+use crate::{Headmaster, Hour};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Timelike};
+use chrono_tz::Tz;
+use failure::{format_err, Error};
+use headmaster::{DetailedActivityStats, State, Summary};
+use priestess::{
+    ActivityGrabber, DailyActivityStats, HourlyActivityStats, HourlySteps, SleepInterval,
+};
+use serde::Deserialize;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// Recorded day to run the debt rules against instead of the FitBit data
+#[derive(Clone, Debug, Deserialize)]
+pub struct Fixture {
+    /// time the day is simulated at, the hours after it are ignored
+    pub now: NaiveDateTime,
+    pub hours: Vec<FixtureHour>,
+    #[serde(default)]
+    pub sleep: Vec<FixtureSleep>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct FixtureHour {
+    pub date: NaiveDate,
+    pub hour: u32,
+    pub active_minutes: u32,
+    /// 60 minutes minus the active ones by default
+    pub sedentary_minutes: Option<u32>,
+    /// sedentary minutes before the first and after the last active one,
+    /// the active minutes are in the middle of the hour by default
+    pub leading_sedentary_minutes: Option<u32>,
+    pub trailing_sedentary_minutes: Option<u32>,
+    pub detailed: Option<DetailedActivityStats>,
+    pub steps: Option<u32>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct FixtureSleep {
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    #[serde(default = "default_main_sleep")]
+    pub is_main_sleep: bool,
+}
+
+fn default_main_sleep() -> bool {
+    true
+}
+
+impl Fixture {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = File::open(path.as_ref())
+            .map_err(|e| format_err!("failed to open {}: {}", path.as_ref().display(), e))?;
+        serde_json::from_reader(file)
+            .map_err(|e| format_err!("invalid fixture {}: {}", path.as_ref().display(), e))
+    }
+
+    /// Hours from `start` to `now` with the active minutes of the comma-separated `pattern`, repeated
+    pub fn from_pattern(
+        pattern: &str,
+        start: NaiveDateTime,
+        now: NaiveDateTime,
+    ) -> Result<Self, Error> {
+        let minutes = pattern
+            .split(',')
+            .map(|m| m.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format_err!("invalid pattern {:?}: {}", pattern, e))?;
+        if let Some(m) = minutes.iter().find(|&&m| m > 60) {
+            return Err(format_err!(
+                "invalid pattern {:?}: {} > 60 minutes",
+                pattern,
+                m
+            ));
+        }
+
+        let mut hours = Vec::new();
+        let mut time = start;
+        for &active_minutes in minutes.iter().cycle() {
+            if time > now {
+                break;
+            }
+            hours.push(FixtureHour {
+                date: time.date(),
+                hour: time.hour(),
+                active_minutes,
+                sedentary_minutes: None,
+                leading_sedentary_minutes: None,
+                trailing_sedentary_minutes: None,
+                detailed: None,
+                steps: None,
+            });
+            time += Duration::hours(1);
+        }
+
+        Ok(Fixture {
+            now,
+            hours,
+            sleep: vec![],
+        })
+    }
+}
+
+/// Serves the fixture data the way FitBit would at the fixture's `now`
+pub struct FixtureGrabber {
+    fixture: Fixture,
+}
+
+impl FixtureGrabber {
+    pub fn new(fixture: Fixture) -> Self {
+        FixtureGrabber { fixture }
+    }
+
+    /// Hours of the `date` started by `now`, with whether they are over
+    fn hours(&self, date: NaiveDate) -> impl Iterator<Item = (&FixtureHour, bool)> {
+        let now = self.fixture.now;
+        self.fixture
+            .hours
+            .iter()
+            .filter(move |h| h.date == date && h.date.and_hms(h.hour, 0, 0) <= now)
+            .map(move |h| (h, h.date.and_hms(h.hour, 0, 0) + Duration::hours(1) <= now))
+    }
+}
+
+impl ActivityGrabber for FixtureGrabber {
+    fn fetch_daily_activity_stats(&self, date: NaiveDate) -> Result<DailyActivityStats, Error> {
+        let hours = self.fetch_hourly_activity(date)?;
+        Ok(DailyActivityStats {
+            sedentary_minutes: hours.iter().map(|h| h.sedentary_minutes).sum(),
+            active_minutes: hours.iter().map(|h| h.active_minutes).sum(),
+            detailed: None,
+        })
+    }
+
+    fn fetch_hourly_activity(&self, date: NaiveDate) -> Result<Vec<HourlyActivityStats>, Error> {
+        Ok(self
+            .hours(date)
+            .map(|(h, complete)| {
+                let sedentary_minutes = h
+                    .sedentary_minutes
+                    .unwrap_or_else(|| 60u32.checked_sub(h.active_minutes).unwrap_or(0));
+                let (leading, trailing) = if h.active_minutes == 0 {
+                    (sedentary_minutes, sedentary_minutes)
+                } else {
+                    (
+                        h.leading_sedentary_minutes.unwrap_or(sedentary_minutes / 2),
+                        h.trailing_sedentary_minutes
+                            .unwrap_or(sedentary_minutes - sedentary_minutes / 2),
+                    )
+                };
+                HourlyActivityStats {
+                    hour: h.hour,
+                    complete,
+                    sedentary_minutes,
+                    active_minutes: h.active_minutes,
+                    detailed: h.detailed,
+                    leading_sedentary_minutes: leading,
+                    trailing_sedentary_minutes: trailing,
+                    longest_sedentary_minutes: u32::max(leading, trailing),
+                }
+            })
+            .collect())
+    }
+
+    fn fetch_hourly_steps(&self, date: NaiveDate) -> Result<Vec<HourlySteps>, Error> {
+        Ok(self
+            .hours(date)
+            .filter_map(|(h, complete)| {
+                h.steps.map(|steps| HourlySteps {
+                    hour: h.hour,
+                    complete,
+                    steps,
+                })
+            })
+            .collect())
+    }
+
+    fn fetch_timezone(&self) -> Result<String, Error> {
+        Ok(chrono_tz::UTC.name().to_owned())
+    }
+
+    fn fetch_sleep_intervals(&self, date: NaiveDate) -> Result<Vec<SleepInterval>, Error> {
+        Ok(self
+            .fixture
+            .sleep
+            .iter()
+            .filter(|s| s.end.date() == date && s.end <= self.fixture.now)
+            .map(|s| SleepInterval {
+                start: s.start,
+                end: s.end,
+                is_main_sleep: s.is_main_sleep,
+            })
+            .collect())
+    }
+}
+
+/// Activity data to simulate the day with
+pub enum Source {
+    Fixture(PathBuf),
+    /// comma-separated active minutes of the hours since the day start
+    Pattern(String),
+}
+
+/// Run the debt rules of the `user` (the first configured one by default) over the `source` data
+/// and print the hourly table with the resulting state. The times are local to the `timezone`,
+/// the user's configured one or UTC by default
+pub fn run(
+    config: &Config,
+    user: Option<String>,
+    source: Source,
+    now: Option<NaiveDateTime>,
+    timezone: Option<Tz>,
+) -> Result<(), Error> {
+    let user = match user {
+        Some(id) => config.users.iter().find(|u| u.id == id),
+        None => config.users.first(),
+    }
+    .ok_or_else(|| format_err!("no such user in the config"))?;
+
+    // Simulation never touches the real user data
+    let data_dir = std::env::temp_dir()
+        .join("headmaster-simulate")
+        .join(&user.id);
+    let auth = user.auth.clone().unwrap_or_else(|| config.auth.clone());
+    let mut master = Headmaster::new(auth, user.clone(), data_dir)?;
+    // FitBit profile is not read, the day is simulated in UTC unless the timezone is known
+    let tz = timezone.or(user.timezone).unwrap_or(chrono_tz::UTC);
+    master.timezone = Some(tz);
+    // Calendars are fetched from the network or the disk at the time of the run, not the simulated one
+    master.calendars.clear();
+
+    let fixture = match source {
+        Source::Fixture(path) => Fixture::load(path)?,
+        Source::Pattern(pattern) => {
+            let now = match now {
+                Some(now) => now,
+                None => master.current_time()?,
+            };
+            // Pattern starts with the configured day
+            let window = master.current_day_window(now, tz);
+            let day = master.user.schedule(window.date.weekday()).day;
+            let start = NaiveDateTime::max(window.date.and_time(day.day_begins_at), window.start);
+            Fixture::from_pattern(&pattern, start, now)?
+        }
+    };
+    let now = now.unwrap_or(fixture.now);
+    master.grabber = Some(Box::new(FixtureGrabber::new(Fixture { now, ..fixture })));
+
+    let (summary, hours) = master.summarize(now)?;
+    print_table(&hours, &summary);
+    Ok(())
+}
+
+fn print_table(hours: &[Hour], summary: &Summary) {
+    println!(
+        "{:<17} {:>8} {:>8} {:>10} {:>9} {:>5}  notes",
+        "hour", "absolute", "weighted", "normalized", "required", "debt"
+    );
+    for h in hours {
+        let mut notes = Vec::new();
+        if !h.complete {
+            notes.push("in progress".to_owned());
+        }
+        if let Some(reason) = h.tracking_disabled_reason {
+            notes.push(format!("{:?}", reason));
+        }
+        if h.sedentary_penalty > 0 {
+            notes.push(format!("sedentary +{}", h.sedentary_penalty));
+        }
+        if h.prepaid_minutes > 0 {
+            notes.push(format!("prepaid {}", h.prepaid_minutes));
+        }
+        if h.forgiven_debt > 0 {
+            notes.push(format!("forgiven {}", h.forgiven_debt));
+        }
+        if h.debt_truncated {
+            notes.push("debt capped".to_owned());
+        }
+
+        println!(
+            "{:<17} {:>8} {:>8} {:>10} {:>9} {:>5}  {}",
+            h.start().format("%Y-%m-%d %H:%M"),
+            h.active_minutes,
+            h.activity(),
+            h.accounted_active_minutes,
+            h.requirement(),
+            h.debt,
+            notes.join(", ")
+        );
+    }
+
+    let state = match summary.state {
+        State::Normal(_) => "Normal",
+        State::DebtWarning(_) => "DebtWarning",
+        State::DebtCollection(_) => "DebtCollection",
+        State::DebtCollectionPaused(_) => "DebtCollectionPaused",
+        State::Paused(_) => "Paused",
+    };
+    println!();
+    println!("state: {}, severity: {}", state, summary.severity);
+    if let Some(deadline) = summary.deadline {
+        println!("debt is collected after: {}", deadline);
+    }
+    if let Some(plan) = &summary.plan {
+        if let Some(required) = plan.required_this_hour {
+            println!("required this hour: {}", required);
+        }
+        if let Some(cleared_at) = plan.cleared_at {
+            println!("debt cleared at: {}", cleared_at);
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use crate::config::{Challenge, Sharing, User};
+use crate::history::History;
+use chrono::{Datelike, Duration, NaiveDate};
+use headmaster::{ChallengeStatus, Leaderboard, Metric, Rank};
+
+/// Team member's data available for the team view
+pub type Member<'a> = (&'a User, &'a History);
+
+/// Aggregates over the user's day logs in some dates range
+#[derive(Debug, Default, Copy, Clone)]
+struct Aggregates {
+    debt_free_hours: u32,
+    active_minutes: u32,
+    longest_streak: u32,
+}
+
+impl Aggregates {
+    fn collect(history: &History, from: NaiveDate, to: NaiveDate) -> Self {
+        let mut aggregates = Aggregates::default();
+        let mut streak = 0;
+
+        for hour in history.range(from, to).flat_map(|(_, day_log)| day_log) {
+            aggregates.active_minutes += hour.active_minutes;
+
+            // Only complete tracked hours are accounted for debt
+            if !hour.complete || hour.tracking_disabled {
+                continue;
+            }
+
+            if hour.debt == 0 {
+                aggregates.debt_free_hours += 1;
+                streak += 1;
+                aggregates.longest_streak = u32::max(aggregates.longest_streak, streak);
+            } else {
+                streak = 0;
+            }
+        }
+
+        aggregates
+    }
+
+    fn get(&self, metric: Metric) -> u32 {
+        match metric {
+            Metric::ActiveMinutes => self.active_minutes,
+            Metric::DebtFreeHours => self.debt_free_hours,
+        }
+    }
+}
+
+/// Rank members over the last week, each ranking lists only the members sharing it
+pub fn leaderboard(members: &[Member], today: NaiveDate) -> Leaderboard {
+    let from = today - Duration::days(6);
+    let aggregates = members
+        .iter()
+        .map(|(user, history)| (*user, Aggregates::collect(history, from, today)))
+        .collect::<Vec<_>>();
+
+    let ranking = |shared: fn(&Sharing) -> bool, value: fn(&Aggregates) -> u32| {
+        let ranks = aggregates
+            .iter()
+            .filter(|(user, _)| shared(&user.sharing))
+            .map(|(user, aggregates)| Rank {
+                user: user.id.clone(),
+                value: value(aggregates),
+            })
+            .collect();
+        sorted(ranks)
+    };
+
+    Leaderboard {
+        from,
+        to: today,
+        debt_free_hours: ranking(|s| s.debt_free_hours, |a| a.debt_free_hours),
+        active_minutes: ranking(|s| s.active_minutes, |a| a.active_minutes),
+        streaks: ranking(|s| s.streaks, |a| a.longest_streak),
+    }
+}
+
+/// Progress of the weekly challenges, which run from Monday till the deadline day inclusive
+pub fn challenges(
+    challenges: &[Challenge],
+    members: &[Member],
+    today: NaiveDate,
+) -> Vec<ChallengeStatus> {
+    let week_start = today - Duration::days(i64::from(today.weekday().num_days_from_monday()));
+
+    challenges
+        .iter()
+        .map(|challenge| {
+            let deadline =
+                week_start + Duration::days(i64::from(challenge.deadline.num_days_from_monday()));
+            let to = std::cmp::min(today, deadline);
+
+            let contributions = members
+                .iter()
+                .filter(|(user, _)| user.sharing.is_shared(challenge.metric))
+                .map(|(user, history)| Rank {
+                    user: user.id.clone(),
+                    value: Aggregates::collect(history, week_start, to).get(challenge.metric),
+                })
+                .collect();
+            let contributions = sorted(contributions);
+            let progress = contributions.iter().map(|rank| rank.value).sum();
+
+            ChallengeStatus {
+                name: challenge.name.clone(),
+                metric: challenge.metric,
+                goal: challenge.goal,
+                progress,
+                from: week_start,
+                deadline,
+                complete: progress >= challenge.goal,
+                contributions,
+            }
+        })
+        .collect()
+}
+
+fn sorted(mut ranks: Vec<Rank>) -> Vec<Rank> {
+    ranks.sort_by(|a, b| b.value.cmp(&a.value).then_with(|| a.user.cmp(&b.user)));
+    ranks
+}
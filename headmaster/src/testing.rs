@@ -0,0 +1,138 @@
+//! Fixtures shared by the tests
+use crate::config::{
+    Auth, Day, DebtPolicyKind, Limits, PauseQuota, Schedule, Sharing, User, Weights,
+};
+use crate::simulate::{Fixture, FixtureGrabber, FixtureHour};
+use crate::{Headmaster, Hour};
+use chrono::{Duration, NaiveDate, NaiveDateTime, Timelike};
+use headmaster::Unit;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// 5 minutes an hour are required, up to 15 minutes of debt
+pub fn limits(policy: DebtPolicyKind) -> Limits {
+    Limits {
+        unit: Unit::Minutes,
+        minimum_active_time: 5,
+        profile: vec![],
+        max_accounted_active_time: 15,
+        debt_limit: 15,
+        max_sedentary_time: None,
+        policy,
+        weights: Weights::default(),
+    }
+}
+
+/// 10:00 to 20:00
+pub fn day() -> Day {
+    Day {
+        day_begins_at: "10:00:00".parse().unwrap(),
+        day_ends_at: "20:00:00".parse().unwrap(),
+        day_length: 10,
+    }
+}
+
+pub fn schedule(policy: DebtPolicyKind) -> Schedule {
+    Schedule {
+        day: day(),
+        limits: limits(policy),
+        tracking_disabled: false,
+    }
+}
+
+/// User with the `day` and the hourly chain `limits` in UTC, with no calendars, credit or decay
+pub fn user() -> User {
+    User {
+        id: "test".to_owned(),
+        api_key: "test".to_owned(),
+        auth: None,
+        timezone: Some(chrono_tz::UTC),
+        mets_thresholds: None,
+        limits: limits(DebtPolicyKind::HourlyChain),
+        day: day(),
+        weekdays: vec![],
+        calendars: vec![],
+        pause: PauseQuota::default(),
+        sharing: Sharing::default(),
+        grace_period_minutes: 0,
+        severity_levels: vec![],
+        bank: None,
+        decay: None,
+    }
+}
+
+/// Date the fixtures are at, a Monday
+pub fn date() -> NaiveDate {
+    NaiveDate::from_ymd(2019, 1, 7)
+}
+
+pub fn at(hour: u32, minute: u32) -> NaiveDateTime {
+    date().and_hms(hour, minute, 0)
+}
+
+/// Normalized complete hours starting at 10:00 with the given active minutes, the last one is in progress
+pub fn hours(active_minutes: &[u32]) -> Vec<Hour> {
+    active_minutes
+        .iter()
+        .enumerate()
+        .map(|(i, &minutes)| Hour {
+            complete: i + 1 < active_minutes.len(),
+            active_minutes: minutes,
+            weighted_active_minutes: minutes,
+            accounted_active_minutes: minutes,
+            required_minutes: 5,
+            ..Hour::new(date(), 10 + i as u32)
+        })
+        .collect()
+}
+
+/// Hours of the fixture starting at the `start` with the given active minutes, no sleep data
+pub fn fixture(now: NaiveDateTime, start: NaiveDateTime, active_minutes: &[u32]) -> Fixture {
+    let hours = active_minutes
+        .iter()
+        .enumerate()
+        .map(|(i, &active_minutes)| {
+            let time = start + Duration::hours(i as i64);
+            FixtureHour {
+                date: time.date(),
+                hour: time.hour(),
+                active_minutes,
+                sedentary_minutes: None,
+                leading_sedentary_minutes: None,
+                trailing_sedentary_minutes: None,
+                detailed: None,
+                steps: None,
+            }
+        })
+        .collect();
+    Fixture {
+        now,
+        hours,
+        sleep: vec![],
+    }
+}
+
+/// Empty directory unique to the test
+pub fn temp_dir() -> PathBuf {
+    static DIRS: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "headmaster-test-{}-{}",
+        std::process::id(),
+        DIRS.fetch_add(1, Ordering::SeqCst)
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Headmaster of the `user` reading the `fixture` data in UTC
+pub fn master(user: User, fixture: Fixture) -> Headmaster {
+    let auth = Auth {
+        client_id: "test".to_owned(),
+        client_secret: "test".to_owned(),
+    };
+    let mut master = Headmaster::new(auth, user, temp_dir()).unwrap();
+    master.timezone = Some(chrono_tz::UTC);
+    master.grabber = Some(Box::new(FixtureGrabber::new(fixture)));
+    master
+}
@@ -1,12 +1,14 @@
 use crate::{
-    ActivityGrabber, DailyActivityStats, DetailedActivityStats, HourlyActivityStats, SleepInterval,
+    ActivityGrabber, DailyActivityStats, DetailedActivityStats, HourlyActivityStats, HourlySteps,
+    MetsThresholds, SleepInterval,
 };
 
 use fitbit::activities::Activities;
 use fitbit::sleep::Sleep;
+use fitbit::user::User;
 use fitbit::{FitbitAuth, FitbitClient};
 
-use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Timelike};
 use fitbit::date::Date;
 
 use failure::{format_err, Error};
@@ -20,6 +22,7 @@ pub use fitbit::Token as FitbitToken;
 pub struct FitbitActivityGrabber {
     client: FitbitClient,
     token: FitbitToken,
+    mets_thresholds: Option<MetsThresholds>,
 }
 
 pub struct FitbitAuthData {
@@ -28,6 +31,18 @@ pub struct FitbitAuthData {
     pub token: Option<FitbitToken>,
 }
 
+/// There's no token to reopen the session with, or it can't be refreshed
+#[derive(Debug)]
+pub struct TokenRequired;
+
+impl std::fmt::Display for TokenRequired {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "FitBit token is missing or expired")
+    }
+}
+
+impl std::error::Error for TokenRequired {}
+
 impl FitbitActivityGrabber {
     /// Attempt to authenticate with Firbit API. This method has 2 modes:
     /// - First auth: authenticate via OAuth2, this will open the browser in order to authenticate.
@@ -35,24 +50,9 @@ impl FitbitActivityGrabber {
     ///   will operate as if it was the first auth attempt.
     #[allow(clippy::new_ret_no_self)]
     pub fn new(adata: &FitbitAuthData) -> Result<Self, Error> {
-        // Reopen session
-        if let Some(token) = adata.token.as_ref() {
-            info!("trying to authenticate with token");
-            let auth = FitbitAuth::new(&adata.id, &adata.secret);
-            // Refresh token to ensure one provided is valid
-            if let Ok(token) = auth
-                .exchange_refresh_token(token.clone())
-                .map_err(|e| error!("{}", e))
-            {
-                info!("refresh token exchanged");
-                // Convert to Fitbit Token
-                let token = FitbitToken::from(token);
-                // This does not send any requests, so any fail is not an auth fail
-                return Ok(FitbitActivityGrabber {
-                    client: FitbitClient::new(&token)?,
-                    token,
-                });
-            }
+        match Self::reopen(adata) {
+            Ok(grabber) => return Ok(grabber),
+            Err(e) => info!("{}", e),
         }
 
         info!("authenticating via OAuth2");
@@ -61,7 +61,38 @@ impl FitbitActivityGrabber {
         let auth = FitbitAuth::new(&adata.id, &adata.secret);
         let token = FitbitToken::from(auth.get_token()?);
         let client = FitbitClient::new(&token)?;
-        Ok(FitbitActivityGrabber { client, token })
+        Ok(FitbitActivityGrabber {
+            client,
+            token,
+            mets_thresholds: None,
+        })
+    }
+
+    /// Refresh the token in FitbitAuthData::token and reopen the existing session, never asks for the user input.
+    /// Fails with `TokenRequired` if there's no valid token
+    pub fn reopen(adata: &FitbitAuthData) -> Result<Self, Error> {
+        let token = adata.token.as_ref().ok_or(TokenRequired)?;
+        info!("trying to authenticate with token");
+        let auth = FitbitAuth::new(&adata.id, &adata.secret);
+        // Refresh token to ensure one provided is valid
+        let token = auth.exchange_refresh_token(token.clone()).map_err(|e| {
+            error!("{}", e);
+            TokenRequired
+        })?;
+        info!("refresh token exchanged");
+        // Convert to Fitbit Token
+        let token = FitbitToken::from(token);
+        // This does not send any requests, so any fail is not an auth fail
+        Ok(FitbitActivityGrabber {
+            client: FitbitClient::new(&token)?,
+            token,
+            mets_thresholds: None,
+        })
+    }
+
+    /// Classify the minutes by their METs instead of the FitBit's activity levels
+    pub fn set_mets_thresholds(&mut self, thresholds: MetsThresholds) {
+        self.mets_thresholds = Some(thresholds);
     }
 
     /// Return auth token
@@ -123,7 +154,14 @@ impl ActivityGrabber for FitbitActivityGrabber {
 
         // Collect results into the hashmap for convenience
         let mut hourly_stats = HashMap::new();
-        for value in time_series {
+        for mut value in time_series {
+            if let Some(thresholds) = &self.mets_thresholds {
+                let mets = value
+                    .mets
+                    .ok_or_else(|| format_err!("missing field 'mets' at {}", value.time))?;
+                value.level = thresholds.level(mets);
+            }
+
             let stat = hourly_stats
                 .entry(value.time.hour())
                 .or_insert(HourlyActivityStats {
@@ -133,12 +171,32 @@ impl ActivityGrabber for FitbitActivityGrabber {
 
             let mut detailed = stat.detailed.take().unwrap_or_default();
 
+            // Track the sedentary stretches, so that they could be joined across the hour boundaries
+            if value.level == 0 {
+                if stat.active_minutes == 0 {
+                    stat.leading_sedentary_minutes += 1;
+                }
+                stat.trailing_sedentary_minutes += 1;
+                stat.longest_sedentary_minutes = u32::max(
+                    stat.longest_sedentary_minutes,
+                    stat.trailing_sedentary_minutes,
+                );
+            } else {
+                stat.trailing_sedentary_minutes = 0;
+            }
+
             match value.level {
                 0 => stat.sedentary_minutes += 1,
                 1 => detailed.lightly_active += 1,
                 2 => detailed.fairly_active += 1,
                 3 => detailed.heavy_active += 1,
-                e => panic!("unexpected activity level {}", e),
+                e => {
+                    return Err(format_err!(
+                        "unexpected activity level {} at {}",
+                        e,
+                        value.time
+                    ))
+                }
             }
 
             stat.active_minutes =
@@ -162,6 +220,58 @@ impl ActivityGrabber for FitbitActivityGrabber {
         Ok(hourly_stats)
     }
 
+    fn fetch_hourly_steps(&self, date: NaiveDate) -> Result<Vec<HourlySteps>, Error> {
+        let response = self
+            .client
+            .get_log_steps_intraday("-", &Date::from(date), "1min")?;
+        let json: Value = serde_json::from_str(&response)?;
+        let dataset = json
+            .get("activities-steps-intraday")
+            .and_then(|v| v.get("dataset"))
+            .ok_or_else(|| format_err!("invalid json"))?;
+        let time_series: Vec<StepsValue> = serde_json::from_value(dataset.to_owned())?;
+
+        let mut hourly_steps = HashMap::new();
+        for value in time_series {
+            let hour = value.time.hour();
+            hourly_steps
+                .entry(hour)
+                .or_insert(HourlySteps {
+                    hour,
+                    ..HourlySteps::default()
+                })
+                .steps += value.value;
+        }
+
+        // sort entries hour-wise and collect into vector
+        let mut hourly_steps = hourly_steps.drain().map(|(_k, v)| v).collect::<Vec<_>>();
+        hourly_steps.sort_by_key(|v| v.hour);
+
+        // set complete flags for finished hours
+        let len = hourly_steps.len();
+        if len != 0 {
+            hourly_steps
+                .iter_mut()
+                .take(len - 1)
+                .for_each(|v| v.complete = true);
+        }
+
+        Ok(hourly_steps)
+    }
+
+    fn fetch_timezone(&self) -> Result<String, Error> {
+        let response = self.client.get_profile()?;
+        let json: Value = serde_json::from_str(&response)?;
+
+        json.get("user")
+            .and_then(|v| v.get("timezone"))
+            .and_then(|v| v.as_str())
+            .map(str::to_owned)
+            .ok_or_else(|| {
+                format_err!("invalid json: expected '{{ \"user\": {{ \"timezone\": ... }} }}'")
+            })
+    }
+
     fn fetch_sleep_intervals(&self, date: NaiveDate) -> Result<Vec<SleepInterval>, Error> {
         let response = self.client.get_sleep_log(&"-", &Date::from(date))?;
         let json: Value = serde_json::from_str(&response)?;
@@ -174,28 +284,22 @@ impl ActivityGrabber for FitbitActivityGrabber {
         let intervals_utc = sleeps.iter().map(|v| {
             let start = v.get("startTime");
             let end = v.get("endTime");
-            start.and_then(|s| end.map(|e| (s, e)))
+            let is_main_sleep = v.get("isMainSleep").and_then(|v| v.as_bool());
+            start.and_then(|s| end.map(|e| (s, e, is_main_sleep.unwrap_or(false))))
         });
 
         let mut intervals = Vec::new();
 
         for interval in intervals_utc {
-            let (start, end) = interval.ok_or_else(|| {
+            let (start, end, is_main_sleep) = interval.ok_or_else(|| {
                 format_err!("invalid json: fields 'startTime' and 'endTime' are missing")
             })?;
-            let mut start: NaiveDateTime = serde_json::from_value(start.to_owned())?;
-            let mut end: NaiveDateTime = serde_json::from_value(end.to_owned())?;
-            // Normalize by current date
-            if start.date().day() != date.day() {
-                start = NaiveDateTime::new(date, NaiveTime::from_hms(0, 0, 0));
-            }
-            if end.date().day() != date.day() {
-                end = NaiveDateTime::new(date, NaiveTime::from_hms(23, 59, 59));
-            }
-
+            let start: NaiveDateTime = serde_json::from_value(start.to_owned())?;
+            let end: NaiveDateTime = serde_json::from_value(end.to_owned())?;
             intervals.push(SleepInterval {
-                start: start.time(),
-                end: end.time(),
+                start,
+                end,
+                is_main_sleep,
             })
         }
 
@@ -205,7 +309,15 @@ impl ActivityGrabber for FitbitActivityGrabber {
 
 struct TimedValue {
     level: u32,
+    /// metabolic equivalent of the minute
+    mets: Option<f32>,
+    time: NaiveTime,
+}
+
+#[derive(Deserialize)]
+struct StepsValue {
     time: NaiveTime,
+    value: u32,
 }
 
 fn parse_json_timed_values(json: &Value) -> Result<Vec<TimedValue>, Error> {
@@ -224,9 +336,15 @@ fn parse_json_timed_values(json: &Value) -> Result<Vec<TimedValue>, Error> {
         let level = object
             .get("level")
             .ok_or_else(|| format_err!("missing field 'level'"))?;
+        // FitBit reports METs multiplied by 10
+        let mets = object
+            .get("mets")
+            .and_then(|v| v.as_f64())
+            .map(|mets| mets as f32 / 10.0);
         timedvalues.push(TimedValue {
             time: serde_json::from_value(time.to_owned())?,
             level: serde_json::from_value(level.to_owned())?,
+            mets,
         })
     }
 
@@ -1,7 +1,10 @@
 mod fitbit_grabber;
 
-pub use crate::fitbit_grabber::{FitbitActivityGrabber, FitbitAuthData, FitbitToken, TokenStore};
+pub use crate::fitbit_grabber::{
+    FitbitActivityGrabber, FitbitAuthData, FitbitToken, TokenRequired, TokenStore,
+};
 use failure::Error;
+use serde::{Deserialize, Serialize};
 
 #[derive(Copy, Clone, Debug)]
 pub struct DailyActivityStats {
@@ -10,7 +13,8 @@ pub struct DailyActivityStats {
     pub detailed: Option<DetailedActivityStats>,
 }
 
-#[derive(Copy, Clone, Debug, Default)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct DetailedActivityStats {
     pub lightly_active: u32,
     pub fairly_active: u32,
@@ -24,12 +28,50 @@ pub struct HourlyActivityStats {
     pub sedentary_minutes: u32,
     pub active_minutes: u32,
     pub detailed: Option<DetailedActivityStats>,
+    /// sedentary minutes in a row since the start of the hour
+    pub leading_sedentary_minutes: u32,
+    /// sedentary minutes in a row up to the last minute of the hour
+    pub trailing_sedentary_minutes: u32,
+    /// longest sedentary stretch within the hour
+    pub longest_sedentary_minutes: u32,
+}
+
+/// Minimal METs of the activity levels: minutes are classified by these instead of the FitBit's levels
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct MetsThresholds {
+    pub lightly_active: f32,
+    pub fairly_active: f32,
+    pub heavy_active: f32,
+}
+
+impl MetsThresholds {
+    /// Activity level of a minute: 0 (sedentary) to 3 (very active)
+    pub fn level(&self, mets: f32) -> u32 {
+        if mets >= self.heavy_active {
+            3
+        } else if mets >= self.fairly_active {
+            2
+        } else if mets >= self.lightly_active {
+            1
+        } else {
+            0
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct HourlySteps {
+    pub hour: u32,
+    pub complete: bool,
+    pub steps: u32,
 }
 
 #[derive(Copy, Clone, Debug)]
 pub struct SleepInterval {
-    pub start: chrono::NaiveTime,
-    pub end: chrono::NaiveTime,
+    pub start: chrono::NaiveDateTime,
+    pub end: chrono::NaiveDateTime,
+    /// night sleep, as opposed to a nap
+    pub is_main_sleep: bool,
 }
 
 pub trait ActivityGrabber {
@@ -41,5 +83,9 @@ pub trait ActivityGrabber {
         &self,
         date: chrono::NaiveDate,
     ) -> Result<Vec<HourlyActivityStats>, Error>;
+    fn fetch_hourly_steps(&self, date: chrono::NaiveDate) -> Result<Vec<HourlySteps>, Error>;
+    /// IANA timezone name from the user's profile
+    fn fetch_timezone(&self) -> Result<String, Error>;
+    /// Sleeps logged for the `date`, these may start on the previous date
     fn fetch_sleep_intervals(&self, date: chrono::NaiveDate) -> Result<Vec<SleepInterval>, Error>;
 }